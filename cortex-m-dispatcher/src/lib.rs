@@ -0,0 +1,176 @@
+//! Priority-preemptive software tasks dispatched from spare Cortex-M interrupts
+//!
+//! Most devices have more interrupt vectors than the application uses. This crate turns spare
+//! device interrupts into software task *levels*: each level has a bounded queue of tasks, a
+//! task being a function pointer and a `usize` payload, and runs them from the handler of its
+//! interrupt.
+//!
+//! [`Dispatcher::spawn`] queues a task and pends the interrupt of its level with `NVIC::pend`.
+//! The NVIC then runs the task as soon as no code of equal or higher priority is running, so
+//! tasks of a higher level preempt tasks of a lower level, and all tasks share the main stack.
+//! The priority of a level is the NVIC priority of its interrupt, set by [`Dispatcher::init`].
+//!
+//! # Usage
+//!
+//! ``` ignore
+//! use cortex_m_dispatcher::Dispatcher;
+//! use pac::{interrupt, Interrupt};
+//!
+//! // level 0 is dispatched from UART4, level 1 from UART5; 8 queued tasks per level
+//! static DISPATCHER: Dispatcher<Interrupt, 2, 8> =
+//!     Dispatcher::new([Interrupt::UART4, Interrupt::UART5]);
+//!
+//! #[entry]
+//! fn main() -> ! {
+//!     let mut p = cortex_m::Peripherals::take().unwrap();
+//!     // NOTE(unsafe) UART4 and UART5 are not used by the application
+//!     unsafe { DISPATCHER.init(&mut p.NVIC, [0xe0, 0x40]) };
+//!
+//!     DISPATCHER.spawn(0, log_sample, 0).unwrap();
+//!     loop {
+//!         cortex_m::asm::wfi();
+//!     }
+//! }
+//!
+//! fn log_sample(sample: usize) {
+//!     // runs at priority 0xe0
+//! }
+//!
+//! #[interrupt]
+//! fn UART4() {
+//!     DISPATCHER.dispatch(0);
+//! }
+//!
+//! #[interrupt]
+//! fn UART5() {
+//!     DISPATCHER.dispatch(1);
+//! }
+//! ```
+//!
+//! The queues are protected by short critical sections (`cortex_m::interrupt::free`), so tasks
+//! can be spawned from any context, including other tasks and interrupt handlers. The
+//! dispatcher relies on the nesting of exceptions, and must only be used on single core systems.
+
+#![deny(missing_docs)]
+#![no_std]
+
+use core::cell::RefCell;
+use core::fmt;
+
+use cortex_m::deferred::CallbackQueue;
+use cortex_m::interrupt::{self, InterruptNumber, Mutex};
+use cortex_m::peripheral::NVIC;
+
+/// A software task: a function and the payload it is called with
+pub type Task = fn(usize);
+
+/// Possible errors of [`Dispatcher::spawn`]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum SpawnError {
+    /// The level does not exist
+    InvalidLevel,
+    /// The queue of the level is full
+    Full,
+}
+
+impl fmt::Display for SpawnError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            SpawnError::InvalidLevel => "the level does not exist",
+            SpawnError::Full => "the queue of the level is full",
+        })
+    }
+}
+
+/// Dispatches software tasks from `LEVELS` spare interrupts of type `I`, queueing up to `N`
+/// tasks per level
+pub struct Dispatcher<I, const LEVELS: usize, const N: usize> {
+    interrupts: [I; LEVELS],
+    queues: [Mutex<RefCell<CallbackQueue<N>>>; LEVELS],
+}
+
+impl<I, const LEVELS: usize, const N: usize> Dispatcher<I, LEVELS, N>
+where
+    I: InterruptNumber,
+{
+    /// Creates a dispatcher whose level `n` is dispatched from `interrupts[n]`
+    ///
+    /// The interrupts must be distinct, and must not be used by the application or by drivers.
+    pub const fn new(interrupts: [I; LEVELS]) -> Self {
+        const { assert!(N != 0, "the queues must have room for at least one task") };
+
+        Dispatcher {
+            interrupts,
+            queues: [const { Mutex::new(RefCell::new(CallbackQueue::new())) }; LEVELS],
+        }
+    }
+
+    /// Sets the NVIC priority of each level to `priorities[n]`, and unmasks their interrupts
+    ///
+    /// Tasks spawned before this call run once it returns.
+    ///
+    /// # Safety
+    ///
+    /// Changing priorities and unmasking interrupts can break priority-based and mask-based
+    /// critical sections. The interrupts must not be used by anything else.
+    pub unsafe fn init(&self, nvic: &mut NVIC, priorities: [u8; LEVELS]) {
+        for (&interrupt, priority) in self.interrupts.iter().zip(priorities) {
+            unsafe {
+                nvic.set_priority(interrupt, priority);
+                NVIC::unmask(interrupt);
+            }
+        }
+    }
+
+    /// Returns the number of levels
+    pub const fn levels(&self) -> usize {
+        LEVELS
+    }
+
+    /// Returns the interrupt that dispatches `level`
+    pub fn interrupt(&self, level: usize) -> Option<I> {
+        self.interrupts.get(level).copied()
+    }
+
+    /// Returns the NVIC priority of `level`, which its tasks run at
+    pub fn priority(&self, level: usize) -> Option<u8> {
+        self.interrupt(level).map(NVIC::get_priority)
+    }
+
+    /// Returns the number of tasks waiting in the queue of `level`
+    pub fn len(&self, level: usize) -> usize {
+        self.queues.get(level).map_or(0, |queue| {
+            interrupt::free(|cs| queue.borrow(cs).borrow().len())
+        })
+    }
+
+    /// Queues `task`, to be called with `payload` at `level`, and pends the interrupt of the
+    /// level
+    ///
+    /// Tasks of the same level run in the order they were spawned. A task spawned at a level
+    /// of higher priority than the current context runs before this returns.
+    pub fn spawn(&self, level: usize, task: Task, payload: usize) -> Result<(), SpawnError> {
+        let (Some(&interrupt), Some(queue)) = (self.interrupts.get(level), self.queues.get(level))
+        else {
+            return Err(SpawnError::InvalidLevel);
+        };
+        interrupt::free(|cs| queue.borrow(cs).borrow_mut().push(task, payload))
+            .map_err(|_| SpawnError::Full)?;
+        NVIC::pend(interrupt);
+        Ok(())
+    }
+
+    /// Runs the tasks queued at `level`, until its queue is empty
+    ///
+    /// This must be called from the handler of the interrupt of the level, and only from
+    /// there. The tasks run with interrupts enabled.
+    pub fn dispatch(&self, level: usize) {
+        let Some(queue) = self.queues.get(level) else {
+            return;
+        };
+        while let Some((task, payload)) = interrupt::free(|cs| queue.borrow(cs).borrow_mut().pop())
+        {
+            task(payload);
+        }
+    }
+}
@@ -0,0 +1,1033 @@
+//! GDB remote serial protocol stub running in the `DebugMonitor` exception
+//!
+//! This crate lets `arm-none-eabi-gdb` debug a running program over any byte transport, such as
+//! a UART, without a debug probe. The stub uses monitor mode debugging: breakpoints, watchpoints
+//! and single-steps raise the `DebugMonitor` exception, whose handler hands control to the stub
+//! until GDB resumes the program.
+//!
+//! Supported operations:
+//!
+//! - reading and writing registers;
+//! - reading and writing memory;
+//! - continuing and single-stepping;
+//! - hardware breakpoints, using the FPB, so code in flash memory can be debugged;
+//! - read, write and access watchpoints, using the DWT.
+//!
+//! # Usage
+//!
+//! Implement [`Transport`] for the serial port, create a [`GdbStub`] and a [`CortexM`] target,
+//! and call [`GdbStub::handle_exception`] from the `DebugMonitor` handler. The program enters the
+//! stub on the first `BKPT` instruction, or whenever the `DebugMonitor` exception is pended, for
+//! example from the receive interrupt of the serial port.
+//!
+//! ``` ignore
+//! use core::cell::RefCell;
+//! use cortex_m::interrupt::Mutex;
+//! use cortex_m_gdbstub::{CortexM, GdbStub};
+//! use cortex_m_rt::{entry, exception, CalleeSavedRegisters, ExceptionFrame};
+//!
+//! static STUB: Mutex<RefCell<Option<(GdbStub<Serial>, CortexM)>>> = Mutex::new(RefCell::new(None));
+//!
+//! #[entry]
+//! fn main() -> ! {
+//!     let mut p = cortex_m::Peripherals::take().unwrap();
+//!     let target = unsafe { CortexM::new(&mut p.DCB, p.FPB, p.DWT) };
+//!     let stub = GdbStub::new(Serial::new(/* .. */));
+//!     cortex_m::interrupt::free(|cs| STUB.borrow(cs).replace(Some((stub, target))));
+//!
+//!     // wait for GDB to connect
+//!     cortex_m::asm::bkpt();
+//!
+//!     loop {
+//!         // ..
+//!     }
+//! }
+//!
+//! #[exception]
+//! unsafe fn DebugMonitor(frame: &mut ExceptionFrame, registers: &mut CalleeSavedRegisters) {
+//!     cortex_m::interrupt::free(|cs| {
+//!         if let Some((stub, target)) = STUB.borrow(cs).borrow_mut().as_mut() {
+//!             unsafe { stub.handle_exception(target, frame, registers) }.ok();
+//!         }
+//!     });
+//! }
+//! ```
+//!
+//! Then connect GDB to the serial port:
+//!
+//! ``` text
+//! $ arm-none-eabi-gdb -ex 'target remote /dev/ttyUSB0' target/thumbv7m-none-eabi/debug/app
+//! ```
+//!
+//! # Limitations
+//!
+//! - `sp` can not be written: it is derived from the location and size of the exception frame.
+//!   The other registers are read from and written to the exception frame and the
+//!   `CalleeSavedRegisters` saved by the `DebugMonitor` trampoline of `cortex-m-rt`.
+//! - Code running at a priority equal to or higher than `DebugMonitor` can not be debugged.
+//! - Memory accesses to unmapped addresses fault.
+//! - Monitor mode debugging is disabled by the processor while a debug probe has enabled halting
+//!   debug.
+//!
+//! **NOTE** This crate is not available on ARMv6-M and ARMv8-M Baseline, which have no
+//! `DebugMonitor` exception.
+
+#![deny(missing_docs)]
+#![no_std]
+
+mod packet;
+mod target;
+
+use cortex_m::peripheral::dwt::AccessType;
+use cortex_m_rt::{CalleeSavedRegisters, ExceptionFrame};
+
+use crate::packet::{PACKET_SIZE, Response, decode_bytes, parse_hex};
+pub use crate::target::{CortexM, StopReason, Target, TargetError};
+
+/// A blocking byte transport to GDB, such as a serial port
+pub trait Transport {
+    /// Transport error
+    type Error;
+
+    /// Reads one byte, waiting until it is available
+    fn read_byte(&mut self) -> Result<u8, Self::Error>;
+
+    /// Writes one byte
+    fn write_byte(&mut self, byte: u8) -> Result<(), Self::Error>;
+
+    /// Waits until all the written bytes have been sent
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+/// Description of the registers sent by the stub, in the order of the `g` packet
+const TARGET_XML: &str = concat!(
+    r#"<?xml version="1.0"?>"#,
+    r#"<!DOCTYPE target SYSTEM "gdb-target.dtd">"#,
+    r#"<target version="1.0">"#,
+    r#"<architecture>arm</architecture>"#,
+    r#"<feature name="org.gnu.gdb.arm.m-profile">"#,
+    r#"<reg name="r0" bitsize="32"/>"#,
+    r#"<reg name="r1" bitsize="32"/>"#,
+    r#"<reg name="r2" bitsize="32"/>"#,
+    r#"<reg name="r3" bitsize="32"/>"#,
+    r#"<reg name="r4" bitsize="32"/>"#,
+    r#"<reg name="r5" bitsize="32"/>"#,
+    r#"<reg name="r6" bitsize="32"/>"#,
+    r#"<reg name="r7" bitsize="32"/>"#,
+    r#"<reg name="r8" bitsize="32"/>"#,
+    r#"<reg name="r9" bitsize="32"/>"#,
+    r#"<reg name="r10" bitsize="32"/>"#,
+    r#"<reg name="r11" bitsize="32"/>"#,
+    r#"<reg name="r12" bitsize="32"/>"#,
+    r#"<reg name="sp" bitsize="32" type="data_ptr"/>"#,
+    r#"<reg name="lr" bitsize="32"/>"#,
+    r#"<reg name="pc" bitsize="32" type="code_ptr"/>"#,
+    r#"<reg name="xpsr" bitsize="32"/>"#,
+    r#"</feature>"#,
+    r#"</target>"#,
+);
+
+/// Number of registers described by `TARGET_XML`
+const NUM_REGISTERS: u32 = 17;
+
+/// Number of bytes of memory transferred at once
+const CHUNK_SIZE: usize = 32;
+
+const SIGINT: u8 = 2;
+const SIGTRAP: u8 = 5;
+
+/// GDB remote serial protocol stub
+pub struct GdbStub<T: Transport> {
+    transport: T,
+    rx: [u8; PACKET_SIZE],
+    tx: [u8; PACKET_SIZE],
+    state: State,
+}
+
+/// Debugging session state kept across `DebugMonitor` exceptions
+struct State {
+    /// GDB is connected and waits for a stop reply when the program stops
+    attached: bool,
+    stop_reason: StopReason,
+    /// GDB asked for a single-step, rather than to continue
+    single_step: bool,
+    /// The stub is stepping over a breakpoint, with breakpoints suspended
+    stepping_over_breakpoint: bool,
+}
+
+/// What to do once a packet has been processed
+enum Action {
+    Reply,
+    Resume,
+    ReplyAndResume,
+}
+
+impl<T: Transport> GdbStub<T> {
+    /// Creates a stub communicating with GDB over `transport`
+    pub const fn new(transport: T) -> Self {
+        GdbStub {
+            transport,
+            rx: [0; PACKET_SIZE],
+            tx: [0; PACKET_SIZE],
+            state: State {
+                attached: false,
+                stop_reason: StopReason::Interrupt,
+                single_step: false,
+                stepping_over_breakpoint: false,
+            },
+        }
+    }
+
+    /// Returns `true` if GDB is connected
+    pub fn is_attached(&self) -> bool {
+        self.state.attached
+    }
+
+    /// Releases the transport
+    pub fn free(self) -> T {
+        self.transport
+    }
+
+    /// Reports the debug event to GDB and processes its commands until it resumes the program
+    ///
+    /// Call this from the `DebugMonitor` handler, with the exception frame of the interrupted
+    /// code and the registers saved next to it.
+    ///
+    /// # Safety
+    ///
+    /// `frame` must be the exception frame stacked on entry to `DebugMonitor`, and `saved` the
+    /// registers saved by the `DebugMonitor` trampoline: GDB can change the registers of the
+    /// interrupted code, and the code executed when the handler returns.
+    pub unsafe fn handle_exception<G: Target>(
+        &mut self,
+        target: &mut G,
+        frame: &mut ExceptionFrame,
+        saved: &mut CalleeSavedRegisters,
+    ) -> Result<(), T::Error> {
+        let mut registers = Registers { frame, saved };
+
+        let reason = target.take_stop_reason();
+
+        if self.state.stepping_over_breakpoint {
+            self.state.stepping_over_breakpoint = false;
+            target.suspend_breakpoints(false);
+            if reason == StopReason::Step && !self.state.single_step {
+                // the breakpoint was stepped over, continue as GDB asked
+                target.set_step(false);
+                return Ok(());
+            }
+        }
+
+        self.state.stop_reason = reason;
+        if self.state.attached {
+            // GDB waits for the reply to its last continue or step packet
+            let mut response = Response::new(&mut self.tx);
+            stop_reply(&mut response, reason);
+            packet::send(&mut self.transport, response.as_bytes())?;
+        }
+
+        loop {
+            let len = packet::receive(&mut self.transport, &mut self.rx)?;
+            self.state.attached = true;
+
+            let mut response = Response::new(&mut self.tx);
+            let action = unsafe {
+                process(
+                    &self.rx[..len],
+                    &mut response,
+                    &mut self.state,
+                    target,
+                    &mut registers,
+                )
+            };
+            match action {
+                Action::Reply => packet::send(&mut self.transport, response.as_bytes())?,
+                Action::Resume => return Ok(()),
+                Action::ReplyAndResume => {
+                    packet::send(&mut self.transport, response.as_bytes())?;
+                    return Ok(());
+                }
+            }
+        }
+    }
+}
+
+/// Processes a single packet, writing its reply to `response`
+///
+/// # Safety
+///
+/// See `GdbStub::handle_exception`
+unsafe fn process<G: Target>(
+    packet: &[u8],
+    response: &mut Response,
+    state: &mut State,
+    target: &mut G,
+    registers: &mut Registers,
+) -> Action {
+    let (&command, arguments) = match packet.split_first() {
+        Some(split) => split,
+        None => return Action::Reply,
+    };
+
+    match command {
+        b'?' => stop_reply(response, state.stop_reason),
+        b'g' => {
+            for n in 0..NUM_REGISTERS {
+                match registers.read(n) {
+                    Some(value) => response.push_hex_le(value),
+                    None => response.push_str("xxxxxxxx"),
+                }
+            }
+        }
+        b'G' => {
+            let mut failed = false;
+            for (n, digits) in (0..NUM_REGISTERS).zip(arguments.chunks(8)) {
+                // registers sent back as unavailable are left unchanged
+                if digits == b"xxxxxxxx" {
+                    continue;
+                }
+                // registers that can't be written, like `sp`, may be sent back unchanged
+                failed |= match parse_register(digits) {
+                    Some(value) => {
+                        !unsafe { registers.write(n, value) } && registers.read(n) != Some(value)
+                    }
+                    None => true,
+                };
+            }
+            if failed {
+                response.error(0x0e);
+            } else {
+                response.push_str("OK");
+            }
+        }
+        b'p' => match parse_hex(arguments).and_then(|n| registers.read(n)) {
+            Some(value) => response.push_hex_le(value),
+            None => response.error(0x0e),
+        },
+        b'P' => {
+            let written = split_once(arguments, b'=').and_then(|(n, value)| {
+                let (n, value) = (parse_hex(n)?, parse_register(value)?);
+                unsafe { registers.write(n, value) }.then_some(())
+            });
+            match written {
+                Some(()) => response.push_str("OK"),
+                None => response.error(0x0e),
+            }
+        }
+        b'm' => match parse_address_length(arguments) {
+            Some((address, length)) => read_memory(response, target, address, length),
+            None => response.error(0x01),
+        },
+        b'M' => match split_once(arguments, b':')
+            .and_then(|(range, data)| Some((parse_address_length(range)?, data)))
+        {
+            Some(((address, length), data)) => {
+                write_memory(response, target, address, length, data)
+            }
+            None => response.error(0x01),
+        },
+        b'c' | b's' => {
+            unsafe { resume(arguments, state, target, registers.frame, command == b's') };
+            return Action::Resume;
+        }
+        b'Z' | b'z' => breakpoint(response, target, command == b'Z', arguments),
+        b'D' => {
+            detach(state, target);
+            response.push_str("OK");
+            return Action::ReplyAndResume;
+        }
+        b'k' => {
+            detach(state, target);
+            return Action::Resume;
+        }
+        b'H' => response.push_str("OK"),
+        b'q' => query(response, arguments),
+        // unsupported packets get an empty reply
+        _ => {}
+    }
+
+    Action::Reply
+}
+
+fn stop_reply(response: &mut Response, reason: StopReason) {
+    match reason {
+        StopReason::Breakpoint | StopReason::Step => {
+            response.push(b'S');
+            response.push_hex_byte(SIGTRAP);
+        }
+        StopReason::Interrupt => {
+            response.push(b'S');
+            response.push_hex_byte(SIGINT);
+        }
+        StopReason::Watchpoint { address, access } => {
+            response.push(b'T');
+            response.push_hex_byte(SIGTRAP);
+            response.push_str(match access {
+                AccessType::WriteOnly => "watch:",
+                AccessType::ReadOnly => "rwatch:",
+                AccessType::ReadWrite => "awatch:",
+            });
+            response.push_hex_be(address);
+            response.push(b';');
+        }
+    }
+}
+
+/// Registers of the interrupted code: the exception frame, and the registers saved next to it
+struct Registers<'a> {
+    frame: &'a mut ExceptionFrame,
+    saved: &'a mut CalleeSavedRegisters,
+}
+
+impl Registers<'_> {
+    /// Returns the value of the stack pointer before the exception frame was stacked
+    ///
+    /// Returns `None` if the saved `EXC_RETURN` is not a valid exception return value, as the
+    /// size of the frame is then unknown.
+    fn stack_pointer(&self) -> Option<u32> {
+        let exc_return = self.saved.exc_return();
+        if exc_return >> 24 != 0xff {
+            return None;
+        }
+        let mut frame_size = if exc_return & (1 << 4) == 0 {
+            // extended frame: `s0`-`s15`, `fpscr` and a reserved word follow the basic frame
+            0x68
+        } else {
+            0x20
+        };
+        if self.frame.xpsr() & (1 << 9) != 0 {
+            // the frame was aligned to 8 bytes
+            frame_size += 4;
+        }
+        Some((&*self.frame as *const ExceptionFrame as usize as u32).wrapping_add(frame_size))
+    }
+
+    fn read(&self, n: u32) -> Option<u32> {
+        let (frame, saved) = (&self.frame, &self.saved);
+        match n {
+            0 => Some(frame.r0()),
+            1 => Some(frame.r1()),
+            2 => Some(frame.r2()),
+            3 => Some(frame.r3()),
+            4 => Some(saved.r4()),
+            5 => Some(saved.r5()),
+            6 => Some(saved.r6()),
+            7 => Some(saved.r7()),
+            8 => Some(saved.r8()),
+            9 => Some(saved.r9()),
+            10 => Some(saved.r10()),
+            11 => Some(saved.r11()),
+            12 => Some(frame.r12()),
+            13 => self.stack_pointer(),
+            14 => Some(frame.lr()),
+            15 => Some(frame.pc()),
+            16 => Some(frame.xpsr()),
+            _ => None,
+        }
+    }
+
+    /// Writes a register, returning `false` if it can't be written
+    unsafe fn write(&mut self, n: u32, value: u32) -> bool {
+        let (frame, saved) = (&mut self.frame, &mut self.saved);
+        unsafe {
+            match n {
+                0 => frame.set_r0(value),
+                1 => frame.set_r1(value),
+                2 => frame.set_r2(value),
+                3 => frame.set_r3(value),
+                4 => saved.set_r4(value),
+                5 => saved.set_r5(value),
+                6 => saved.set_r6(value),
+                7 => saved.set_r7(value),
+                8 => saved.set_r8(value),
+                9 => saved.set_r9(value),
+                10 => saved.set_r10(value),
+                11 => saved.set_r11(value),
+                12 => frame.set_r12(value),
+                14 => frame.set_lr(value),
+                15 => frame.set_pc(value),
+                16 => frame.set_xpsr(value),
+                _ => return false,
+            }
+        }
+        true
+    }
+}
+
+/// Parses a register value in target byte order
+fn parse_register(digits: &[u8]) -> Option<u32> {
+    let mut bytes = [0; 4];
+    decode_bytes(digits, &mut bytes)?;
+    Some(u32::from_le_bytes(bytes))
+}
+
+fn split_once(bytes: &[u8], separator: u8) -> Option<(&[u8], &[u8])> {
+    let position = bytes.iter().position(|&byte| byte == separator)?;
+    Some((&bytes[..position], &bytes[position + 1..]))
+}
+
+/// Parses the `address,length` arguments of memory packets
+fn parse_address_length(arguments: &[u8]) -> Option<(u32, u32)> {
+    let (address, length) = split_once(arguments, b',')?;
+    Some((parse_hex(address)?, parse_hex(length)?))
+}
+
+fn read_memory<G: Target>(response: &mut Response, target: &mut G, address: u32, length: u32) {
+    // two digits per byte; GDB asks for the rest of a truncated read
+    let length = (length as usize).min(response.remaining() / 2);
+    let mut chunk = [0; CHUNK_SIZE];
+    let mut offset = 0;
+    while offset < length {
+        let size = (length - offset).min(CHUNK_SIZE);
+        let chunk = &mut chunk[..size];
+        if let Err(e) = target.read_memory(address.wrapping_add(offset as u32), chunk) {
+            response.error(e.code());
+            return;
+        }
+        chunk.iter().for_each(|&byte| response.push_hex_byte(byte));
+        offset += size;
+    }
+}
+
+fn write_memory<G: Target>(
+    response: &mut Response,
+    target: &mut G,
+    address: u32,
+    length: u32,
+    data: &[u8],
+) {
+    if data.len() != 2 * length as usize {
+        response.error(0x01);
+        return;
+    }
+
+    let mut chunk = [0; CHUNK_SIZE];
+    for (index, digits) in data.chunks(2 * CHUNK_SIZE).enumerate() {
+        let chunk = &mut chunk[..digits.len() / 2];
+        if decode_bytes(digits, chunk).is_none() {
+            response.error(0x01);
+            return;
+        }
+        let offset = (index * CHUNK_SIZE) as u32;
+        if let Err(e) = target.write_memory(address.wrapping_add(offset), chunk) {
+            response.error(e.code());
+            return;
+        }
+    }
+    response.push_str("OK");
+}
+
+/// Prepares the target to continue or single-step once the stub returns
+unsafe fn resume<G: Target>(
+    arguments: &[u8],
+    state: &mut State,
+    target: &mut G,
+    frame: &mut ExceptionFrame,
+    step: bool,
+) {
+    if let Some(address) = parse_hex(arguments) {
+        unsafe { frame.set_pc(address) };
+    } else if state.stop_reason == StopReason::Breakpoint && !target.has_breakpoint(frame.pc()) {
+        // skip the `BKPT` instruction that stopped the program, or it would stop again
+        let mut instruction = [0; 2];
+        if target.read_memory(frame.pc(), &mut instruction).is_ok() && instruction[1] == 0xbe {
+            unsafe { frame.set_pc(frame.pc().wrapping_add(2)) };
+        }
+    }
+
+    state.single_step = step;
+    if target.has_breakpoint(frame.pc()) {
+        // step over the breakpoint before enabling it again
+        target.suspend_breakpoints(true);
+        state.stepping_over_breakpoint = true;
+        target.set_step(true);
+    } else {
+        target.set_step(step);
+    }
+}
+
+fn detach<G: Target>(state: &mut State, target: &mut G) {
+    if state.stepping_over_breakpoint {
+        state.stepping_over_breakpoint = false;
+        target.suspend_breakpoints(false);
+    }
+    target.set_step(false);
+    state.attached = false;
+}
+
+/// Handles the `Z` and `z` packets: `Z<type>,<address>,<kind>`
+fn breakpoint<G: Target>(response: &mut Response, target: &mut G, insert: bool, arguments: &[u8]) {
+    let mut fields = arguments.split(|&byte| byte == b',');
+    let (kind, address, size) = match (
+        fields.next(),
+        fields.next().and_then(parse_hex),
+        fields.next().and_then(parse_hex),
+    ) {
+        (Some(kind), Some(address), Some(size)) => (kind, address, size),
+        _ => {
+            response.error(0x01);
+            return;
+        }
+    };
+
+    let access = match kind {
+        // software breakpoints are implemented with hardware breakpoints, as flash memory can't
+        // be patched
+        b"0" | b"1" => None,
+        b"2" => Some(AccessType::WriteOnly),
+        b"3" => Some(AccessType::ReadOnly),
+        b"4" => Some(AccessType::ReadWrite),
+        // unsupported breakpoint types get an empty reply
+        _ => return,
+    };
+
+    let result = match (access, insert) {
+        (None, true) => target.add_breakpoint(address),
+        (None, false) => target.remove_breakpoint(address),
+        (Some(access), true) => target.add_watchpoint(address, size, access),
+        (Some(access), false) => target.remove_watchpoint(address, size, access),
+    };
+    match result {
+        Ok(()) => response.push_str("OK"),
+        Err(e) => response.error(e.code()),
+    }
+}
+
+/// Handles the `q` packets
+fn query(response: &mut Response, arguments: &[u8]) {
+    if arguments.starts_with(b"Supported") {
+        response.push_str("PacketSize=");
+        response.push_hex_byte((PACKET_SIZE >> 8) as u8);
+        response.push_hex_byte(PACKET_SIZE as u8);
+        response.push_str(";qXfer:features:read+");
+    } else if arguments == b"Attached" {
+        response.push(b'1');
+    } else if let Some(range) = arguments.strip_prefix(b"Xfer:features:read:target.xml:") {
+        let (offset, length) = match parse_address_length(range) {
+            Some(range) => range,
+            None => {
+                response.error(0x01);
+                return;
+            }
+        };
+        let xml = TARGET_XML.as_bytes();
+        let start = (offset as usize).min(xml.len());
+        let end = start + (length as usize).min(response.remaining() - 1);
+        if end >= xml.len() {
+            response.push(b'l');
+            xml[start..].iter().for_each(|&byte| response.push(byte));
+        } else {
+            response.push(b'm');
+            xml[start..end].iter().for_each(|&byte| response.push(byte));
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    extern crate std;
+
+    use std::collections::VecDeque;
+    use std::format;
+    use std::string::String;
+    use std::vec::Vec;
+
+    use super::*;
+
+    pub(crate) struct FakeTransport {
+        input: VecDeque<u8>,
+        pub(crate) output: Vec<u8>,
+    }
+
+    impl FakeTransport {
+        pub(crate) fn new(input: &[u8]) -> Self {
+            FakeTransport {
+                input: input.iter().copied().collect(),
+                output: Vec::new(),
+            }
+        }
+    }
+
+    impl Transport for FakeTransport {
+        type Error = ();
+
+        fn read_byte(&mut self) -> Result<u8, ()> {
+            self.input.pop_front().ok_or(())
+        }
+
+        fn write_byte(&mut self, byte: u8) -> Result<(), ()> {
+            self.output.push(byte);
+            Ok(())
+        }
+    }
+
+    const RAM: u32 = 0x2000_0000;
+
+    struct FakeTarget {
+        memory: [u8; 16],
+        stop_reason: StopReason,
+        breakpoints: Vec<u32>,
+        watchpoints: Vec<(u32, u32, AccessType)>,
+        suspended: bool,
+        step: bool,
+    }
+
+    impl FakeTarget {
+        fn new(stop_reason: StopReason) -> Self {
+            FakeTarget {
+                memory: [0; 16],
+                stop_reason,
+                breakpoints: Vec::new(),
+                watchpoints: Vec::new(),
+                suspended: false,
+                step: false,
+            }
+        }
+
+        fn range(&self, address: u32, len: usize) -> Result<core::ops::Range<usize>, TargetError> {
+            let start = address.wrapping_sub(RAM) as usize;
+            if start + len <= self.memory.len() {
+                Ok(start..start + len)
+            } else {
+                Err(TargetError::InvalidAddress)
+            }
+        }
+    }
+
+    impl Target for FakeTarget {
+        fn take_stop_reason(&mut self) -> StopReason {
+            self.stop_reason
+        }
+
+        fn read_memory(&mut self, address: u32, data: &mut [u8]) -> Result<(), TargetError> {
+            let range = self.range(address, data.len())?;
+            data.copy_from_slice(&self.memory[range]);
+            Ok(())
+        }
+
+        fn write_memory(&mut self, address: u32, data: &[u8]) -> Result<(), TargetError> {
+            let range = self.range(address, data.len())?;
+            self.memory[range].copy_from_slice(data);
+            Ok(())
+        }
+
+        fn add_breakpoint(&mut self, address: u32) -> Result<(), TargetError> {
+            self.breakpoints.push(address);
+            Ok(())
+        }
+
+        fn remove_breakpoint(&mut self, address: u32) -> Result<(), TargetError> {
+            let index = self
+                .breakpoints
+                .iter()
+                .position(|&b| b == address)
+                .ok_or(TargetError::NotFound)?;
+            self.breakpoints.remove(index);
+            Ok(())
+        }
+
+        fn has_breakpoint(&self, address: u32) -> bool {
+            self.breakpoints.contains(&address)
+        }
+
+        fn suspend_breakpoints(&mut self, suspend: bool) {
+            self.suspended = suspend;
+        }
+
+        fn add_watchpoint(
+            &mut self,
+            address: u32,
+            size: u32,
+            access: AccessType,
+        ) -> Result<(), TargetError> {
+            self.watchpoints.push((address, size, access));
+            Ok(())
+        }
+
+        fn remove_watchpoint(
+            &mut self,
+            address: u32,
+            size: u32,
+            access: AccessType,
+        ) -> Result<(), TargetError> {
+            let index = self
+                .watchpoints
+                .iter()
+                .position(|&w| w == (address, size, access))
+                .ok_or(TargetError::NotFound)?;
+            self.watchpoints.remove(index);
+            Ok(())
+        }
+
+        fn set_step(&mut self, step: bool) {
+            self.step = step;
+        }
+    }
+
+    fn frame(pc: u32) -> ExceptionFrame {
+        let registers = [0, 1, 2, 3, 12, 0x0800_0001, pc, 0x0100_0000];
+        // NOTE(unsafe) `ExceptionFrame` is `repr(C)` with eight `u32` fields
+        unsafe { core::mem::transmute::<[u32; 8], ExceptionFrame>(registers) }
+    }
+
+    /// Registers saved on entry from thread mode on the process stack, without FP state
+    fn saved() -> CalleeSavedRegisters {
+        saved_with(0xffff_fffd)
+    }
+
+    fn saved_with(exc_return: u32) -> CalleeSavedRegisters {
+        let registers = [4, 5, 6, 7, 8, 9, 10, 11, exc_return];
+        // NOTE(unsafe) `CalleeSavedRegisters` is `repr(C)` with nine `u32` fields
+        unsafe { core::mem::transmute::<[u32; 9], CalleeSavedRegisters>(registers) }
+    }
+
+    /// Frames `payload` as GDB would, followed by the acknowledgement of the reply
+    fn packet(payload: &str) -> String {
+        let checksum = payload
+            .bytes()
+            .fold(0u8, |sum, byte| sum.wrapping_add(byte));
+        format!("${}#{:02x}+", payload, checksum)
+    }
+
+    /// An empty command stands for the acknowledgement of a stop reply
+    fn session(commands: &[&str]) -> FakeTransport {
+        let input: String = commands
+            .iter()
+            .map(|&command| match command {
+                "" => String::from("+"),
+                command => packet(command),
+            })
+            .collect();
+        FakeTransport::new(input.as_bytes())
+    }
+
+    /// Extracts the payloads of the packets sent by the stub
+    fn replies(transport: &FakeTransport) -> Vec<String> {
+        let output = String::from_utf8(transport.output.clone()).unwrap();
+        output
+            .split('$')
+            .skip(1)
+            .map(|packet| String::from(packet.split('#').next().unwrap()))
+            .collect()
+    }
+
+    #[test]
+    fn registers() {
+        let mut stub = GdbStub::new(session(&[
+            "?",
+            "p0",
+            "p7",
+            "P1=78563412",
+            "P7=00000020",
+            "Pd=00000020",
+            "c",
+        ]));
+        let mut target = FakeTarget::new(StopReason::Interrupt);
+        let mut frame = frame(0x0800_0100);
+        let mut saved = saved();
+
+        unsafe { stub.handle_exception(&mut target, &mut frame, &mut saved) }.unwrap();
+
+        assert_eq!(
+            replies(&stub.transport),
+            ["S02", "00000000", "07000000", "OK", "OK", "E0e"]
+        );
+        assert_eq!(frame.r1(), 0x1234_5678);
+        assert_eq!(saved.r7(), 0x2000_0000);
+        assert!(stub.is_attached());
+    }
+
+    #[test]
+    fn stack_pointer() {
+        let mut target = FakeTarget::new(StopReason::Interrupt);
+        let mut frame = frame(0x0800_0100);
+        let address = &frame as *const ExceptionFrame as usize as u32;
+
+        // basic frame, extended frame with FP state, and an aligned extended frame
+        for (exc_return, xpsr, size) in [
+            (0xffff_fffd, 0x0100_0000, 0x20),
+            (0xffff_ffed, 0x0100_0000, 0x68),
+            (0xffff_ffe9, 0x0100_0200, 0x6c),
+        ] {
+            let mut stub = GdbStub::new(session(&["?", "pd", "c"]));
+            let mut saved = saved_with(exc_return);
+            unsafe { frame.set_xpsr(xpsr) };
+
+            unsafe { stub.handle_exception(&mut target, &mut frame, &mut saved) }.unwrap();
+
+            let sp = std::format!("{:08x}", (address + size).swap_bytes());
+            assert_eq!(replies(&stub.transport), ["S02", sp.as_str()]);
+        }
+
+        // not an `EXC_RETURN` value: the size of the frame is unknown
+        let mut stub = GdbStub::new(session(&["?", "pd", "c"]));
+        let mut saved = saved_with(0);
+
+        unsafe { stub.handle_exception(&mut target, &mut frame, &mut saved) }.unwrap();
+
+        assert_eq!(replies(&stub.transport), ["S02", "E0e"]);
+    }
+
+    #[test]
+    fn read_all_registers() {
+        let mut stub = GdbStub::new(session(&["g", "c"]));
+        let mut target = FakeTarget::new(StopReason::Interrupt);
+        let mut frame = frame(0x0800_0100);
+        let mut saved = saved();
+
+        unsafe { stub.handle_exception(&mut target, &mut frame, &mut saved) }.unwrap();
+
+        let replies = replies(&stub.transport);
+        let registers = &replies[0];
+        assert_eq!(registers.len(), 8 * NUM_REGISTERS as usize);
+        assert_eq!(&registers[..8], "00000000");
+        assert_eq!(&registers[4 * 8..5 * 8], "04000000");
+        assert_eq!(&registers[11 * 8..12 * 8], "0b000000");
+        assert_eq!(&registers[15 * 8..16 * 8], "00010008");
+        assert_eq!(&registers[16 * 8..], "00000001");
+    }
+
+    #[test]
+    fn write_all_registers() {
+        let mut stub = GdbStub::new(session(&["g", "c"]));
+        let mut target = FakeTarget::new(StopReason::Interrupt);
+        let mut frame = frame(0x0800_0100);
+        let mut saved = saved();
+        unsafe { stub.handle_exception(&mut target, &mut frame, &mut saved) }.unwrap();
+        let registers = replies(&stub.transport).remove(0);
+
+        // writing back the registers read, with a new r0, succeeds
+        let unchanged = format!("78563412{}", &registers[8..]);
+        // changing sp can't be done
+        let sp = format!("{}00000020{}", &unchanged[..13 * 8], &unchanged[14 * 8..]);
+        let mut stub = GdbStub::new(session(&[
+            &format!("G{}", unchanged),
+            &format!("G{}", sp),
+            "c",
+        ]));
+        unsafe { stub.handle_exception(&mut target, &mut frame, &mut saved) }.unwrap();
+
+        assert_eq!(replies(&stub.transport), ["OK", "E0e"]);
+        assert_eq!(frame.r0(), 0x1234_5678);
+    }
+
+    #[test]
+    fn memory() {
+        let mut stub = GdbStub::new(session(&[
+            "M20000004,2:beef",
+            "m20000003,4",
+            "m10000000,4",
+            "c",
+        ]));
+        let mut target = FakeTarget::new(StopReason::Interrupt);
+        let mut frame = frame(0x0800_0100);
+        let mut saved = saved();
+
+        unsafe { stub.handle_exception(&mut target, &mut frame, &mut saved) }.unwrap();
+
+        assert_eq!(replies(&stub.transport), ["OK", "00beef00", "E0e"]);
+        assert_eq!(target.memory[4..6], [0xbe, 0xef]);
+    }
+
+    #[test]
+    fn continue_over_breakpoint() {
+        let mut stub = GdbStub::new(session(&["Z1,08000100,2", "c"]));
+        let mut target = FakeTarget::new(StopReason::Interrupt);
+        let mut frame = frame(0x0800_0100);
+        let mut saved = saved();
+
+        unsafe { stub.handle_exception(&mut target, &mut frame, &mut saved) }.unwrap();
+
+        assert_eq!(replies(&stub.transport), ["OK"]);
+        assert_eq!(target.breakpoints, [0x0800_0100]);
+        assert!(target.suspended);
+        assert!(target.step);
+
+        // the breakpoint has been stepped over: resume silently
+        stub.transport = FakeTransport::new(b"");
+        target.stop_reason = StopReason::Step;
+        unsafe { stub.handle_exception(&mut target, &mut frame, &mut saved) }.unwrap();
+
+        assert!(stub.transport.output.is_empty());
+        assert!(!target.suspended);
+        assert!(!target.step);
+    }
+
+    #[test]
+    fn single_step() {
+        let mut stub = GdbStub::new(session(&["s"]));
+        let mut target = FakeTarget::new(StopReason::Interrupt);
+        let mut frame = frame(0x0800_0100);
+        let mut saved = saved();
+
+        unsafe { stub.handle_exception(&mut target, &mut frame, &mut saved) }.unwrap();
+        assert!(target.step);
+
+        // GDB acknowledges the stop reply
+        stub.transport = session(&["", "c"]);
+        target.stop_reason = StopReason::Step;
+        unsafe { stub.handle_exception(&mut target, &mut frame, &mut saved) }.unwrap();
+
+        assert_eq!(replies(&stub.transport), ["S05"]);
+        assert!(!target.step);
+    }
+
+    #[test]
+    fn skip_bkpt_instruction() {
+        let mut stub = GdbStub::new(session(&["c"]));
+        let mut target = FakeTarget::new(StopReason::Breakpoint);
+        target.memory[8..10].copy_from_slice(&[0x00, 0xbe]);
+        let mut frame = frame(RAM + 8);
+        let mut saved = saved();
+
+        unsafe { stub.handle_exception(&mut target, &mut frame, &mut saved) }.unwrap();
+
+        assert_eq!(frame.pc(), RAM + 10);
+    }
+
+    #[test]
+    fn watchpoint() {
+        let mut stub = GdbStub::new(session(&["Z2,20000000,4", "c"]));
+        let mut target = FakeTarget::new(StopReason::Interrupt);
+        let mut frame = frame(0x0800_0100);
+        let mut saved = saved();
+
+        unsafe { stub.handle_exception(&mut target, &mut frame, &mut saved) }.unwrap();
+        assert_eq!(target.watchpoints, [(RAM, 4, AccessType::WriteOnly)]);
+
+        stub.transport = session(&["", "z2,20000000,4", "D"]);
+        target.stop_reason = StopReason::Watchpoint {
+            address: RAM,
+            access: AccessType::WriteOnly,
+        };
+        unsafe { stub.handle_exception(&mut target, &mut frame, &mut saved) }.unwrap();
+
+        assert_eq!(replies(&stub.transport), ["T05watch:20000000;", "OK", "OK"]);
+        assert!(target.watchpoints.is_empty());
+        assert!(!stub.is_attached());
+    }
+
+    #[test]
+    fn target_description() {
+        let mut stub = GdbStub::new(session(&[
+            "qSupported:multiprocess+;swbreak+",
+            "qXfer:features:read:target.xml:0,10",
+            "qXfer:features:read:target.xml:700,100",
+            "vMustReplyEmpty",
+            "c",
+        ]));
+        let mut target = FakeTarget::new(StopReason::Interrupt);
+        let mut frame = frame(0x0800_0100);
+        let mut saved = saved();
+
+        unsafe { stub.handle_exception(&mut target, &mut frame, &mut saved) }.unwrap();
+
+        let replies = replies(&stub.transport);
+        assert_eq!(replies[0], "PacketSize=0100;qXfer:features:read+");
+        assert_eq!(replies[1], "m<?xml version=\"1");
+        assert_eq!(replies[2], "l");
+        assert_eq!(replies[3], "");
+    }
+}
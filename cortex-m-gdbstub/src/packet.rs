@@ -0,0 +1,282 @@
+//! Framing of remote serial protocol packets
+
+use crate::Transport;
+
+/// Size of the packet buffers, as advertised to GDB
+pub(crate) const PACKET_SIZE: usize = 256;
+
+/// Receives a packet into `buffer`, acknowledging it, and returns its length
+///
+/// Bytes outside of packets, such as acknowledgements and the `^C` interrupt request, are
+/// discarded. Packets with a bad checksum or that do not fit in `buffer` are rejected with a `-`
+/// so that GDB retransmits them.
+pub(crate) fn receive<T: Transport>(
+    transport: &mut T,
+    buffer: &mut [u8; PACKET_SIZE],
+) -> Result<usize, T::Error> {
+    // cleared when a `$` was already read by the previous iteration
+    let mut need_start = true;
+    'packet: loop {
+        if need_start {
+            while transport.read_byte()? != b'$' {}
+        }
+        need_start = true;
+
+        let mut len = 0;
+        let mut checksum = 0u8;
+        let mut overflow = false;
+        loop {
+            let byte = transport.read_byte()?;
+            match byte {
+                b'#' => break,
+                // a new packet started before the end of this one, e.g. a retransmission
+                b'$' => {
+                    need_start = false;
+                    continue 'packet;
+                }
+                _ => {}
+            }
+            checksum = checksum.wrapping_add(byte);
+
+            let byte = if byte == b'}' {
+                let escaped = transport.read_byte()?;
+                checksum = checksum.wrapping_add(escaped);
+                escaped ^ 0x20
+            } else {
+                byte
+            };
+
+            if len < buffer.len() {
+                buffer[len] = byte;
+                len += 1;
+            } else {
+                overflow = true;
+            }
+        }
+
+        let high = hex_digit(transport.read_byte()?);
+        let low = hex_digit(transport.read_byte()?);
+        match (high, low) {
+            (Some(high), Some(low)) if !overflow && (high << 4 | low) == checksum => {
+                transport.write_byte(b'+')?;
+                transport.flush()?;
+                return Ok(len);
+            }
+            _ => {
+                transport.write_byte(b'-')?;
+                transport.flush()?;
+            }
+        }
+    }
+}
+
+/// Sends the packet `payload`, retransmitting it until GDB acknowledges it
+pub(crate) fn send<T: Transport>(transport: &mut T, payload: &[u8]) -> Result<(), T::Error> {
+    loop {
+        transport.write_byte(b'$')?;
+        let mut checksum = 0u8;
+        for &byte in payload {
+            if matches!(byte, b'#' | b'$' | b'}' | b'*') {
+                transport.write_byte(b'}')?;
+                transport.write_byte(byte ^ 0x20)?;
+                checksum = checksum.wrapping_add(b'}').wrapping_add(byte ^ 0x20);
+            } else {
+                transport.write_byte(byte)?;
+                checksum = checksum.wrapping_add(byte);
+            }
+        }
+        transport.write_byte(b'#')?;
+        transport.write_byte(HEX_DIGITS[usize::from(checksum >> 4)])?;
+        transport.write_byte(HEX_DIGITS[usize::from(checksum & 0xf)])?;
+        transport.flush()?;
+
+        loop {
+            match transport.read_byte()? {
+                b'+' => return Ok(()),
+                b'-' => break,
+                // GDB never sends a packet before acknowledging ours
+                _ => {}
+            }
+        }
+    }
+}
+
+const HEX_DIGITS: &[u8; 16] = b"0123456789abcdef";
+
+/// Decodes a single hexadecimal digit
+pub(crate) fn hex_digit(byte: u8) -> Option<u8> {
+    match byte {
+        b'0'..=b'9' => Some(byte - b'0'),
+        b'a'..=b'f' => Some(byte - b'a' + 10),
+        b'A'..=b'F' => Some(byte - b'A' + 10),
+        _ => None,
+    }
+}
+
+/// Parses a big-endian hexadecimal number, as used for addresses and lengths
+pub(crate) fn parse_hex(digits: &[u8]) -> Option<u32> {
+    if digits.is_empty() || digits.len() > 8 {
+        return None;
+    }
+    digits.iter().try_fold(0u32, |value, &byte| {
+        Some(value << 4 | u32::from(hex_digit(byte)?))
+    })
+}
+
+/// Decodes pairs of hexadecimal digits into `bytes`, returning `None` on malformed input
+pub(crate) fn decode_bytes(digits: &[u8], bytes: &mut [u8]) -> Option<()> {
+    if digits.len() != 2 * bytes.len() {
+        return None;
+    }
+    for (byte, pair) in bytes.iter_mut().zip(digits.chunks_exact(2)) {
+        *byte = hex_digit(pair[0])? << 4 | hex_digit(pair[1])?;
+    }
+    Some(())
+}
+
+/// A packet being built, truncated to `PACKET_SIZE` bytes
+pub(crate) struct Response<'a> {
+    buffer: &'a mut [u8; PACKET_SIZE],
+    len: usize,
+}
+
+impl<'a> Response<'a> {
+    pub(crate) fn new(buffer: &'a mut [u8; PACKET_SIZE]) -> Self {
+        Response { buffer, len: 0 }
+    }
+
+    pub(crate) fn as_bytes(&self) -> &[u8] {
+        &self.buffer[..self.len]
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.len = 0;
+    }
+
+    /// Returns the number of bytes that can still be appended
+    pub(crate) fn remaining(&self) -> usize {
+        PACKET_SIZE - self.len
+    }
+
+    pub(crate) fn push(&mut self, byte: u8) {
+        if self.len < PACKET_SIZE {
+            self.buffer[self.len] = byte;
+            self.len += 1;
+        }
+    }
+
+    pub(crate) fn push_str(&mut self, s: &str) {
+        s.bytes().for_each(|byte| self.push(byte));
+    }
+
+    /// Appends `byte` as two hexadecimal digits
+    pub(crate) fn push_hex_byte(&mut self, byte: u8) {
+        self.push(HEX_DIGITS[usize::from(byte >> 4)]);
+        self.push(HEX_DIGITS[usize::from(byte & 0xf)]);
+    }
+
+    /// Appends `value` in target (little-endian) byte order, as used for register values
+    pub(crate) fn push_hex_le(&mut self, value: u32) {
+        value
+            .to_le_bytes()
+            .iter()
+            .for_each(|&byte| self.push_hex_byte(byte));
+    }
+
+    /// Appends `value` as a big-endian hexadecimal number, as used for addresses
+    pub(crate) fn push_hex_be(&mut self, value: u32) {
+        value
+            .to_be_bytes()
+            .iter()
+            .for_each(|&byte| self.push_hex_byte(byte));
+    }
+
+    /// Appends the error reply `Enn`
+    pub(crate) fn error(&mut self, code: u8) {
+        self.clear();
+        self.push(b'E');
+        self.push_hex_byte(code);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    extern crate std;
+
+    use super::*;
+    use crate::test::FakeTransport;
+
+    #[test]
+    fn receive_acknowledges_valid_packet() {
+        let mut transport = FakeTransport::new(b"+$g#67");
+        let mut buffer = [0; PACKET_SIZE];
+
+        let len = receive(&mut transport, &mut buffer).unwrap();
+
+        assert_eq!(&buffer[..len], b"g");
+        assert_eq!(transport.output, b"+");
+    }
+
+    #[test]
+    fn receive_rejects_bad_checksum() {
+        let mut transport = FakeTransport::new(b"$g#00$g#67");
+        let mut buffer = [0; PACKET_SIZE];
+
+        let len = receive(&mut transport, &mut buffer).unwrap();
+
+        assert_eq!(&buffer[..len], b"g");
+        assert_eq!(transport.output, b"-+");
+    }
+
+    #[test]
+    fn receive_restarts_on_start_of_packet() {
+        // the first packet is cut short by a retransmission
+        let mut transport = FakeTransport::new(b"$ab$qC#b4");
+        let mut buffer = [0; PACKET_SIZE];
+
+        let len = receive(&mut transport, &mut buffer).unwrap();
+
+        assert_eq!(&buffer[..len], b"qC");
+        assert_eq!(transport.output, b"+");
+    }
+
+    #[test]
+    fn receive_unescapes() {
+        // `X0,1:}]` writes the byte `}`, escaped as `}]`
+        let mut transport = FakeTransport::new(b"$X0,1:}]#f9");
+        let mut buffer = [0; PACKET_SIZE];
+
+        let len = receive(&mut transport, &mut buffer).unwrap();
+
+        assert_eq!(&buffer[..len], b"X0,1:}");
+    }
+
+    #[test]
+    fn send_escapes_and_retransmits() {
+        let mut transport = FakeTransport::new(b"-+");
+
+        send(&mut transport, b"a#").unwrap();
+
+        assert_eq!(transport.output, b"$a}\x03#e1$a}\x03#e1");
+    }
+
+    #[test]
+    fn hex() {
+        assert_eq!(parse_hex(b"2000fF00"), Some(0x2000_ff00));
+        assert_eq!(parse_hex(b""), None);
+        assert_eq!(parse_hex(b"123456789"), None);
+        assert_eq!(parse_hex(b"12g"), None);
+
+        let mut bytes = [0; 2];
+        assert_eq!(decode_bytes(b"be0a", &mut bytes), Some(()));
+        assert_eq!(bytes, [0xbe, 0x0a]);
+        assert_eq!(decode_bytes(b"be0", &mut bytes), None);
+
+        let mut buffer = [0; PACKET_SIZE];
+        let mut response = Response::new(&mut buffer);
+        response.push_hex_le(0x0800_0101);
+        response.push(b',');
+        response.push_hex_be(0x0800_0101);
+        assert_eq!(response.as_bytes(), b"01010008,08000101");
+    }
+}
@@ -0,0 +1,297 @@
+//! Access to the debugged processor
+
+use core::ptr;
+use cortex_m::asm;
+use cortex_m::debug_monitor::{self, DebugEvent};
+use cortex_m::peripheral::dwt::{AccessType, Watchpoint};
+use cortex_m::peripheral::{DCB, DWT, FPB};
+
+/// Why the processor stopped and entered the stub
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum StopReason {
+    /// A breakpoint was hit, either a `BKPT` instruction or a hardware breakpoint
+    Breakpoint,
+    /// A watchpoint was hit
+    Watchpoint {
+        /// First address of the watched region
+        address: u32,
+        /// Accesses matched by the watchpoint
+        access: AccessType,
+    },
+    /// A single-step completed
+    Step,
+    /// The stub was entered on request, e.g. by pending the `DebugMonitor` exception
+    Interrupt,
+}
+
+/// Possible errors of target operations, reported to GDB as error replies
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum TargetError {
+    /// The address can not be accessed, or the breakpoint or watchpoint is not supported at
+    /// this address
+    InvalidAddress,
+    /// All hardware breakpoints or watchpoints are in use
+    NoResources,
+    /// No breakpoint or watchpoint is set at this address
+    NotFound,
+}
+
+impl TargetError {
+    /// Returns the error number sent to GDB
+    pub(crate) fn code(self) -> u8 {
+        match self {
+            TargetError::InvalidAddress => 0x0e,
+            TargetError::NoResources => 0x1c,
+            TargetError::NotFound => 0x02,
+        }
+    }
+}
+
+/// Debug resources of the processor used by the stub
+///
+/// [`CortexM`] implements this trait with the FPB and DWT. Other implementations can for example
+/// simulate a processor to test a transport on a host.
+pub trait Target {
+    /// Returns the reason for entering the stub, and clears it
+    fn take_stop_reason(&mut self) -> StopReason;
+
+    /// Reads memory starting at `address` into `data`
+    fn read_memory(&mut self, address: u32, data: &mut [u8]) -> Result<(), TargetError>;
+
+    /// Writes `data` to memory starting at `address`
+    fn write_memory(&mut self, address: u32, data: &[u8]) -> Result<(), TargetError>;
+
+    /// Sets a breakpoint on the instruction at `address`
+    fn add_breakpoint(&mut self, address: u32) -> Result<(), TargetError>;
+
+    /// Removes the breakpoint at `address`
+    fn remove_breakpoint(&mut self, address: u32) -> Result<(), TargetError>;
+
+    /// Returns `true` if a breakpoint is set at `address`
+    fn has_breakpoint(&self, address: u32) -> bool;
+
+    /// Temporarily disables all breakpoints, or enables them again
+    ///
+    /// The stub uses this to step over a breakpoint before resuming.
+    fn suspend_breakpoints(&mut self, suspend: bool);
+
+    /// Sets a watchpoint over `size` bytes at `address`
+    fn add_watchpoint(
+        &mut self,
+        address: u32,
+        size: u32,
+        access: AccessType,
+    ) -> Result<(), TargetError>;
+
+    /// Removes the watchpoint over `size` bytes at `address`
+    fn remove_watchpoint(
+        &mut self,
+        address: u32,
+        size: u32,
+        access: AccessType,
+    ) -> Result<(), TargetError>;
+
+    /// Enables or disables single-stepping once the stub returns
+    fn set_step(&mut self, step: bool);
+}
+
+/// Maximum number of FPB comparators used for breakpoints
+const MAX_BREAKPOINTS: usize = 8;
+/// Maximum number of DWT comparators used for watchpoints
+const MAX_WATCHPOINTS: usize = 4;
+
+/// A [`Target`] driving the debug resources of the processor running the stub
+///
+/// Breakpoints use the FPB code comparators and watchpoints use the DWT comparators, so code in
+/// flash memory can be debugged.
+pub struct CortexM {
+    fpb: FPB,
+    dwt: DWT,
+    breakpoints: [Option<u32>; MAX_BREAKPOINTS],
+    watchpoints: [Option<Watchpoint>; MAX_WATCHPOINTS],
+}
+
+impl CortexM {
+    /// Takes the FPB and the DWT and enables monitor mode debugging
+    ///
+    /// # Safety
+    ///
+    /// The attached debugger can read and write any memory and register of the processor. Memory
+    /// accesses to unmapped addresses fault.
+    pub unsafe fn new(dcb: &mut DCB, mut fpb: FPB, dwt: DWT) -> Self {
+        dcb.enable_trace();
+        fpb.enable();
+        dcb.enable_debug_monitor();
+
+        CortexM {
+            fpb,
+            dwt,
+            breakpoints: [None; MAX_BREAKPOINTS],
+            watchpoints: [None; MAX_WATCHPOINTS],
+        }
+    }
+
+    /// Disables monitor mode debugging and releases the FPB and the DWT
+    pub fn free(mut self, dcb: &mut DCB) -> (FPB, DWT) {
+        dcb.disable_debug_monitor();
+        for (comparator, breakpoint) in self.breakpoints.iter().enumerate() {
+            if breakpoint.is_some() {
+                self.fpb.clear_comparator(comparator as u8).ok();
+            }
+        }
+        for (comparator, watchpoint) in self.watchpoints.iter().enumerate() {
+            if watchpoint.is_some() {
+                self.dwt.clear_watchpoint(comparator as u8).ok();
+            }
+        }
+        (self.fpb, self.dwt)
+    }
+
+    fn num_breakpoints() -> usize {
+        usize::from(FPB::num_code_comparators()).min(MAX_BREAKPOINTS)
+    }
+
+    fn num_watchpoints() -> usize {
+        usize::from(DWT::num_comp()).min(MAX_WATCHPOINTS)
+    }
+}
+
+impl Target for CortexM {
+    fn take_stop_reason(&mut self) -> StopReason {
+        match debug_monitor::take_event() {
+            Some(DebugEvent::Watchpoint) => {
+                for (comparator, watchpoint) in self.watchpoints.iter().enumerate() {
+                    if let Some(watchpoint) = watchpoint {
+                        if DWT::has_matched(comparator as u8) {
+                            return StopReason::Watchpoint {
+                                address: watchpoint.address,
+                                access: watchpoint.access,
+                            };
+                        }
+                    }
+                }
+                // no comparator of ours matched: don't report a breakpoint that doesn't exist
+                StopReason::Interrupt
+            }
+            Some(DebugEvent::Step) => StopReason::Step,
+            Some(DebugEvent::Breakpoint) => StopReason::Breakpoint,
+            Some(DebugEvent::External) | Some(DebugEvent::VectorCatch) | None => {
+                StopReason::Interrupt
+            }
+        }
+    }
+
+    fn read_memory(&mut self, address: u32, data: &mut [u8]) -> Result<(), TargetError> {
+        for (offset, byte) in data.iter_mut().enumerate() {
+            let address = address.wrapping_add(offset as u32);
+            // NOTE(unsafe) the user accepted arbitrary memory accesses in `CortexM::new`
+            *byte = unsafe { ptr::read_volatile(address as *const u8) };
+        }
+        Ok(())
+    }
+
+    fn write_memory(&mut self, address: u32, data: &[u8]) -> Result<(), TargetError> {
+        for (offset, &byte) in data.iter().enumerate() {
+            let address = address.wrapping_add(offset as u32);
+            // NOTE(unsafe) the user accepted arbitrary memory accesses in `CortexM::new`
+            unsafe { ptr::write_volatile(address as *mut u8, byte) };
+        }
+        // the written memory may contain code
+        asm::dsb();
+        asm::isb();
+        Ok(())
+    }
+
+    fn add_breakpoint(&mut self, address: u32) -> Result<(), TargetError> {
+        if self.has_breakpoint(address) {
+            return Ok(());
+        }
+        let comparator = self.breakpoints[..Self::num_breakpoints()]
+            .iter()
+            .position(Option::is_none)
+            .ok_or(TargetError::NoResources)?;
+        self.fpb
+            .set_breakpoint(comparator as u8, address)
+            .map_err(|_| TargetError::InvalidAddress)?;
+        self.breakpoints[comparator] = Some(address);
+        Ok(())
+    }
+
+    fn remove_breakpoint(&mut self, address: u32) -> Result<(), TargetError> {
+        let comparator = self
+            .breakpoints
+            .iter()
+            .position(|&breakpoint| breakpoint == Some(address))
+            .ok_or(TargetError::NotFound)?;
+        self.fpb
+            .clear_comparator(comparator as u8)
+            .map_err(|_| TargetError::NotFound)?;
+        self.breakpoints[comparator] = None;
+        Ok(())
+    }
+
+    fn has_breakpoint(&self, address: u32) -> bool {
+        self.breakpoints.contains(&Some(address))
+    }
+
+    fn suspend_breakpoints(&mut self, suspend: bool) {
+        if suspend {
+            self.fpb.disable();
+        } else {
+            self.fpb.enable();
+        }
+    }
+
+    fn add_watchpoint(
+        &mut self,
+        address: u32,
+        size: u32,
+        access: AccessType,
+    ) -> Result<(), TargetError> {
+        let watchpoint = Watchpoint {
+            address,
+            size,
+            access,
+        };
+        let comparator = self.watchpoints[..Self::num_watchpoints()]
+            .iter()
+            .position(Option::is_none)
+            .ok_or(TargetError::NoResources)?;
+        self.dwt
+            .set_watchpoint(comparator as u8, watchpoint)
+            .map_err(|_| TargetError::InvalidAddress)?;
+        self.watchpoints[comparator] = Some(watchpoint);
+        Ok(())
+    }
+
+    fn remove_watchpoint(
+        &mut self,
+        address: u32,
+        size: u32,
+        access: AccessType,
+    ) -> Result<(), TargetError> {
+        let watchpoint = Watchpoint {
+            address,
+            size,
+            access,
+        };
+        let comparator = self
+            .watchpoints
+            .iter()
+            .position(|&w| w == Some(watchpoint))
+            .ok_or(TargetError::NotFound)?;
+        self.dwt
+            .clear_watchpoint(comparator as u8)
+            .map_err(|_| TargetError::NotFound)?;
+        self.watchpoints[comparator] = None;
+        Ok(())
+    }
+
+    fn set_step(&mut self, step: bool) {
+        if step {
+            debug_monitor::step();
+        } else {
+            debug_monitor::resume();
+        }
+    }
+}
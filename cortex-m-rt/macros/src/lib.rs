@@ -118,6 +118,7 @@ pub fn entry(args: TokenStream, input: TokenStream) -> TokenStream {
 enum Exception {
     DefaultHandler,
     HardFault(HardFaultArgs),
+    DebugMonitor,
     NonMaskableInt,
     Other,
 }
@@ -127,6 +128,7 @@ impl Display for Exception {
         match self {
             Exception::DefaultHandler => write!(f, "`DefaultHandler`"),
             Exception::HardFault(_) => write!(f, "`HardFault` handler"),
+            Exception::DebugMonitor => write!(f, "`DebugMonitor` handler"),
             Exception::NonMaskableInt => write!(f, "`NonMaskableInt` handler"),
             Exception::Other => write!(f, "Other exception handler"),
         }
@@ -221,6 +223,17 @@ pub fn exception(args: TokenStream, input: TokenStream) -> TokenStream {
             }
             Exception::NonMaskableInt
         }
+        // A `DebugMonitor` handler that takes an argument receives the stacked exception frame
+        // through a trampoline, like `HardFault`. Without arguments it is a regular handler.
+        "DebugMonitor" if !f.sig.inputs.is_empty() => {
+            if !args.is_empty() {
+                return parse::Error::new(Span::call_site(), "This attribute accepts no arguments")
+                    .to_compile_error()
+                    .into();
+            }
+
+            Exception::DebugMonitor
+        }
         // NOTE that at this point we don't check if the exception is available on the target (e.g.
         // MemoryManagement is not available on Cortex-M0)
         "MemoryManagement" | "BusFault" | "UsageFault" | "SecureFault" | "SVCall"
@@ -249,14 +262,14 @@ pub fn exception(args: TokenStream, input: TokenStream) -> TokenStream {
                     .to_compile_error()
                     .into();
             }
-            Exception::Other => {}
+            Exception::DebugMonitor | Exception::Other => {}
         }
     }
 
     // Emit a reference to the `Exception` variant corresponding to our exception.
     // This will fail compilation when the target doesn't have that exception.
     let assertion = match exn {
-        Exception::Other => {
+        Exception::DebugMonitor | Exception::Other => {
             quote! {
                 const _: () = {
                     let _ = ::cortex_m_rt::Exception::#ident;
@@ -420,6 +433,102 @@ pub fn exception(args: TokenStream, input: TokenStream) -> TokenStream {
                 )
             }
         }
+        Exception::DebugMonitor => {
+            let valid_signature = f.sig.constness.is_none()
+                && f.vis == Visibility::Inherited
+                && f.sig.abi.is_none()
+                && (f.sig.inputs.len() == 1 || f.sig.inputs.len() == 2)
+                && f.sig.inputs.iter().all(|input| match input {
+                    FnArg::Typed(arg) => match arg.ty.as_ref() {
+                        Type::Reference(r) => r.lifetime.is_none(),
+                        _ => false,
+                    },
+                    _ => false,
+                })
+                && f.sig.generics.params.is_empty()
+                && f.sig.generics.where_clause.is_none()
+                && f.sig.variadic.is_none()
+                && match f.sig.output {
+                    ReturnType::Default => true,
+                    ReturnType::Type(_, ref ty) => match **ty {
+                        Type::Tuple(ref tuple) => tuple.elems.is_empty(),
+                        Type::Never(..) => true,
+                        _ => false,
+                    },
+                };
+
+            if !valid_signature {
+                return parse::Error::new(
+                    fspan,
+                    "`DebugMonitor` handler with arguments must have signature \
+                     `[unsafe] fn(&[mut] ExceptionFrame[, &[mut] CalleeSavedRegisters]) [-> !]`",
+                )
+                .to_compile_error()
+                .into();
+            }
+
+            f.sig.ident = Ident::new(&format!("__cortex_m_rt_{}", f.sig.ident), Span::call_site());
+            let tramp_ident = Ident::new(&format!("{}_trampoline", f.sig.ident), Span::call_site());
+            let ident = &f.sig.ident;
+            let call = if f.sig.inputs.len() == 2 {
+                quote!(#ident(frame, registers))
+            } else {
+                quote!(#ident(frame))
+            };
+
+            let (ref cfgs, ref attrs) = extract_cfgs(f.attrs.clone());
+
+            quote!(
+                #(#cfgs)*
+                #(#attrs)*
+                #[doc(hidden)]
+                #[export_name = "_DebugMonitor"]
+                #[allow(unused_unsafe, unused_variables)]
+                unsafe extern "C" fn #tramp_ident(
+                    frame: &mut ::cortex_m_rt::ExceptionFrame,
+                    registers: &mut ::cortex_m_rt::CalleeSavedRegisters,
+                ) {
+                    unsafe { #call }
+                }
+
+                #f
+
+                // DebugMonitor exceptions are bounced through this trampoline which grabs the stack
+                // pointer at the time of the exception and passes it to the user's handler in r0.
+                // Depending on the stack mode in EXC_RETURN, fetches stack from either MSP or PSP.
+                // The trampoline then pushes r4-r11 and EXC_RETURN, keeping the stack 8-byte
+                // aligned, and passes them to the handler in r1 as a `CalleeSavedRegisters`. They
+                // are restored, with the changes made by the handler, before returning from the
+                // exception.
+                core::arch::global_asm!(
+                    ".cfi_sections .debug_frame
+                    .section .text.DebugMonitor, \"ax\"
+                    .global DebugMonitor
+                    .type DebugMonitor,%function
+                    .thumb_func
+                    .cfi_startproc
+                    DebugMonitor:",
+                       "tst lr, #4
+                        ite eq
+                        mrseq r0, MSP
+                        mrsne r0, PSP
+                        sub sp, sp, #4
+                        .cfi_adjust_cfa_offset 4
+                        push {{r4-r11, lr}}
+                        .cfi_adjust_cfa_offset 36
+                        .cfi_rel_offset lr, 32
+                        mov r1, sp
+                        bl _DebugMonitor
+                        pop {{r4-r11, lr}}
+                        .cfi_adjust_cfa_offset -36
+                        add sp, sp, #4
+                        .cfi_adjust_cfa_offset -4
+                        bx lr",
+                    ".cfi_endproc
+                    .size DebugMonitor, . - DebugMonitor",
+                );
+            )
+        }
         Exception::NonMaskableInt | Exception::Other => {
             let valid_signature = f.sig.constness.is_none()
                 && f.vis == Visibility::Inherited
@@ -823,6 +823,19 @@ pub use macros::entry;
 /// To maintain backwards compatibility the attribute can be used without trampoline parameter (`#[exception]`),
 /// which sets the trampoline to true.
 ///
+/// ## DebugMonitor handler
+///
+/// `#[exception] fn DebugMonitor(..` can optionally take the frame stacked by the exception entry,
+/// in which case the handler must have signature `[unsafe] fn(&[mut] ExceptionFrame) [-> !]` or
+/// `[unsafe] fn(&[mut] ExceptionFrame, &[mut] CalleeSavedRegisters) [-> !]`. Like for `HardFault`,
+/// the exception is then bounced through a trampoline that passes the stack pointer at the time of
+/// the exception to the handler. The trampoline also saves `r4`-`r11` and `EXC_RETURN` into a
+/// [`CalleeSavedRegisters`] block, passed as the optional second argument, so the handler sees all
+/// the core registers of the interrupted code. Unlike `HardFault`, the handler may return,
+/// resuming the interrupted code with the (possibly modified) stacked and saved registers.
+///
+/// Without arguments, `DebugMonitor` is handled like the other handlers below.
+///
 /// ## Default handler
 ///
 /// `#[exception] unsafe fn DefaultHandler(..` sets the *default* handler. All exceptions which have
@@ -1115,6 +1128,196 @@ impl fmt::Debug for ExceptionFrame {
     }
 }
 
+/// Registers saved by the `DebugMonitor` trampoline, next to the [`ExceptionFrame`].
+///
+/// The exception entry does not stack the callee-saved registers `r4`-`r11`: the trampoline pushes
+/// them, together with the `EXC_RETURN` value of the exception, and restores them from this block
+/// when the handler returns. See the `DebugMonitor` handler section of
+/// [`exception`](macro@exception).
+#[derive(Clone, Copy)]
+#[repr(C)]
+#[allow(dead_code)]
+pub struct CalleeSavedRegisters {
+    r4: u32,
+    r5: u32,
+    r6: u32,
+    r7: u32,
+    r8: u32,
+    r9: u32,
+    r10: u32,
+    r11: u32,
+    exc_return: u32,
+}
+
+impl CalleeSavedRegisters {
+    /// Returns the value of (general purpose) register 4.
+    #[inline(always)]
+    pub fn r4(&self) -> u32 {
+        self.r4
+    }
+
+    /// Returns the value of (general purpose) register 5.
+    #[inline(always)]
+    pub fn r5(&self) -> u32 {
+        self.r5
+    }
+
+    /// Returns the value of (general purpose) register 6.
+    #[inline(always)]
+    pub fn r6(&self) -> u32 {
+        self.r6
+    }
+
+    /// Returns the value of (general purpose) register 7.
+    #[inline(always)]
+    pub fn r7(&self) -> u32 {
+        self.r7
+    }
+
+    /// Returns the value of (general purpose) register 8.
+    #[inline(always)]
+    pub fn r8(&self) -> u32 {
+        self.r8
+    }
+
+    /// Returns the value of (general purpose) register 9.
+    #[inline(always)]
+    pub fn r9(&self) -> u32 {
+        self.r9
+    }
+
+    /// Returns the value of (general purpose) register 10.
+    #[inline(always)]
+    pub fn r10(&self) -> u32 {
+        self.r10
+    }
+
+    /// Returns the value of (general purpose) register 11.
+    #[inline(always)]
+    pub fn r11(&self) -> u32 {
+        self.r11
+    }
+
+    /// Returns the `EXC_RETURN` value of the exception.
+    ///
+    /// Bit 4 is clear when the [`ExceptionFrame`] is an extended frame, which also holds the
+    /// floating-point context, and bit 2 is set when it was stacked on the process stack.
+    #[inline(always)]
+    pub fn exc_return(&self) -> u32 {
+        self.exc_return
+    }
+
+    /// Sets the saved value of (general purpose) register 4.
+    ///
+    /// # Safety
+    ///
+    /// This affects the `r4` register of the preempted code, which must not rely on it getting
+    /// restored to its previous value.
+    #[inline(always)]
+    pub unsafe fn set_r4(&mut self, value: u32) {
+        self.r4 = value;
+    }
+
+    /// Sets the saved value of (general purpose) register 5.
+    ///
+    /// # Safety
+    ///
+    /// This affects the `r5` register of the preempted code, which must not rely on it getting
+    /// restored to its previous value.
+    #[inline(always)]
+    pub unsafe fn set_r5(&mut self, value: u32) {
+        self.r5 = value;
+    }
+
+    /// Sets the saved value of (general purpose) register 6.
+    ///
+    /// # Safety
+    ///
+    /// This affects the `r6` register of the preempted code, which must not rely on it getting
+    /// restored to its previous value.
+    #[inline(always)]
+    pub unsafe fn set_r6(&mut self, value: u32) {
+        self.r6 = value;
+    }
+
+    /// Sets the saved value of (general purpose) register 7.
+    ///
+    /// # Safety
+    ///
+    /// This affects the `r7` register of the preempted code, which must not rely on it getting
+    /// restored to its previous value.
+    #[inline(always)]
+    pub unsafe fn set_r7(&mut self, value: u32) {
+        self.r7 = value;
+    }
+
+    /// Sets the saved value of (general purpose) register 8.
+    ///
+    /// # Safety
+    ///
+    /// This affects the `r8` register of the preempted code, which must not rely on it getting
+    /// restored to its previous value.
+    #[inline(always)]
+    pub unsafe fn set_r8(&mut self, value: u32) {
+        self.r8 = value;
+    }
+
+    /// Sets the saved value of (general purpose) register 9.
+    ///
+    /// # Safety
+    ///
+    /// This affects the `r9` register of the preempted code, which must not rely on it getting
+    /// restored to its previous value.
+    #[inline(always)]
+    pub unsafe fn set_r9(&mut self, value: u32) {
+        self.r9 = value;
+    }
+
+    /// Sets the saved value of (general purpose) register 10.
+    ///
+    /// # Safety
+    ///
+    /// This affects the `r10` register of the preempted code, which must not rely on it getting
+    /// restored to its previous value.
+    #[inline(always)]
+    pub unsafe fn set_r10(&mut self, value: u32) {
+        self.r10 = value;
+    }
+
+    /// Sets the saved value of (general purpose) register 11.
+    ///
+    /// # Safety
+    ///
+    /// This affects the `r11` register of the preempted code, which must not rely on it getting
+    /// restored to its previous value.
+    #[inline(always)]
+    pub unsafe fn set_r11(&mut self, value: u32) {
+        self.r11 = value;
+    }
+}
+
+impl fmt::Debug for CalleeSavedRegisters {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        struct Hex(u32);
+        impl fmt::Debug for Hex {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "0x{:08x}", self.0)
+            }
+        }
+        f.debug_struct("CalleeSavedRegisters")
+            .field("r4", &Hex(self.r4))
+            .field("r5", &Hex(self.r5))
+            .field("r6", &Hex(self.r6))
+            .field("r7", &Hex(self.r7))
+            .field("r8", &Hex(self.r8))
+            .field("r9", &Hex(self.r9))
+            .field("r10", &Hex(self.r10))
+            .field("r11", &Hex(self.r11))
+            .field("exc_return", &Hex(self.exc_return))
+            .finish()
+    }
+}
+
 /// Returns a pointer to the start of the heap
 ///
 /// The returned pointer is guaranteed to be 4-byte aligned.
@@ -0,0 +1,16 @@
+#![no_main]
+#![no_std]
+
+extern crate cortex_m_rt;
+extern crate panic_halt;
+
+use cortex_m_rt::{entry, exception, ExceptionFrame};
+
+#[entry]
+fn foo() -> ! {
+    loop {}
+}
+
+#[exception]
+fn DebugMonitor(_ef: &ExceptionFrame, undef: u32) {}
+//~^ ERROR `DebugMonitor` handler with arguments must have signature `[unsafe] fn(&[mut] ExceptionFrame[, &[mut] CalleeSavedRegisters]) [-> !]`
@@ -0,0 +1,553 @@
+//! Decoder for the ITM and DWT trace packets of Cortex-M microcontrollers
+//!
+//! This crate turns the byte stream of the trace port, usually captured from the SWO pin, into
+//! typed [`TracePacket`]s: the data written to ITM stimulus ports, e.g. by `iprintln!`, and the
+//! timestamp, exception trace, PC sampling and data trace packets generated by the DWT.
+//!
+//! The decoder is streaming: bytes can be fed as they are received, and packets split across
+//! reads are reassembled.
+//!
+//! The stream must not be formatted by the TPIU, see `TPIU::set_formatter` in the `cortex-m`
+//! crate.
+//!
+//! # Examples
+//!
+//! ```
+//! use cortex_m_trace_decoder::{Decoder, TracePacket};
+//!
+//! let mut decoder = Decoder::new();
+//! // sync packet, then "Hi" written to stimulus port 0 with a single 16-bit write
+//! let bytes = [0x00, 0x00, 0x00, 0x00, 0x00, 0x80, 0x02, b'H', b'i'];
+//! let mut packets = decoder.decode(bytes);
+//!
+//! assert_eq!(packets.next(), Some(Ok(TracePacket::Sync)));
+//! assert_eq!(
+//!     packets.next(),
+//!     Some(Ok(TracePacket::Instrumentation {
+//!         port: 0,
+//!         value: u32::from(u16::from_le_bytes(*b"Hi")),
+//!         size: 2,
+//!     }))
+//! );
+//! assert_eq!(packets.next(), None);
+//! ```
+//!
+//! # Features
+//!
+//! - `alloc`: adds [`Decoder::decode_all`], which collects the packets into a `Vec`.
+//! - `std`: implements `std::error::Error` for [`DecodeError`]. Implies `alloc`.
+
+#![deny(missing_docs)]
+#![no_std]
+
+#[cfg(feature = "alloc")]
+extern crate alloc;
+#[cfg(feature = "std")]
+extern crate std;
+
+mod packet;
+
+use core::fmt;
+
+pub use crate::packet::{
+    DataAccess, EventCounterWrap, ExceptionAction, TimestampRelation, TracePacket,
+};
+
+/// Minimum number of zero bytes preceding the `0x80` byte of a synchronization packet
+const SYNC_ZEROS: u32 = 5;
+
+/// Maximum size of a packet in bytes, reached by 64-bit global timestamps
+const MAX_PACKET_SIZE: usize = 7;
+
+/// Possible errors while decoding a trace stream
+///
+/// The decoder resumes with the next byte, which may not be the start of a packet: packets are
+/// only reliably recognized again after the next synchronization packet.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum DecodeError {
+    /// The header byte does not start any known packet
+    InvalidHeader(u8),
+    /// The packet starting with this header byte has an invalid size or payload
+    MalformedPacket(u8),
+    /// Zero bytes that are not followed by a valid synchronization packet
+    InvalidSync,
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DecodeError::InvalidHeader(header) => write!(f, "invalid header {:#04x}", header),
+            DecodeError::MalformedPacket(header) => {
+                write!(f, "malformed packet with header {:#04x}", header)
+            }
+            DecodeError::InvalidSync => f.write_str("invalid synchronization packet"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for DecodeError {}
+
+/// Streaming trace packet decoder
+#[derive(Clone, Debug, Default)]
+pub struct Decoder {
+    buffer: [u8; MAX_PACKET_SIZE],
+    len: usize,
+    zeros: u32,
+    /// Stimulus port page selected by the last extension packet
+    page: u8,
+}
+
+impl Decoder {
+    /// Creates a decoder expecting a packet boundary
+    pub const fn new() -> Self {
+        Decoder {
+            buffer: [0; MAX_PACKET_SIZE],
+            len: 0,
+            zeros: 0,
+            page: 0,
+        }
+    }
+
+    /// Feeds one byte to the decoder, returning the packet it completes, if any
+    pub fn push(&mut self, byte: u8) -> Option<Result<TracePacket, DecodeError>> {
+        if self.len == 0 {
+            if byte == 0x00 {
+                self.zeros += 1;
+                return None;
+            }
+            if self.zeros != 0 {
+                let zeros = core::mem::take(&mut self.zeros);
+                return Some(if byte == 0x80 && zeros >= SYNC_ZEROS {
+                    Ok(TracePacket::Sync)
+                } else {
+                    Err(DecodeError::InvalidSync)
+                });
+            }
+        }
+
+        self.buffer[self.len] = byte;
+        self.len += 1;
+
+        match parse(&self.buffer[..self.len], self.page) {
+            Ok(None) if self.len < MAX_PACKET_SIZE => None,
+            Ok(None) => {
+                self.len = 0;
+                Some(Err(DecodeError::MalformedPacket(self.buffer[0])))
+            }
+            Ok(Some(packet)) => {
+                self.len = 0;
+                if let TracePacket::Extension {
+                    info,
+                    hardware: false,
+                } = packet
+                {
+                    self.page = (info & 0b111) as u8;
+                }
+                Some(Ok(packet))
+            }
+            Err(e) => {
+                self.len = 0;
+                Some(Err(e))
+            }
+        }
+    }
+
+    /// Returns an iterator over the packets decoded from `bytes`
+    ///
+    /// A packet that is incomplete at the end of `bytes` is completed by later calls.
+    pub fn decode<I>(&mut self, bytes: I) -> Packets<'_, I::IntoIter>
+    where
+        I: IntoIterator<Item = u8>,
+    {
+        Packets {
+            decoder: self,
+            bytes: bytes.into_iter(),
+        }
+    }
+
+    /// Decodes all the packets completed by `bytes`
+    #[cfg(feature = "alloc")]
+    pub fn decode_all(
+        &mut self,
+        bytes: &[u8],
+    ) -> alloc::vec::Vec<Result<TracePacket, DecodeError>> {
+        self.decode(bytes.iter().copied()).collect()
+    }
+}
+
+/// Iterator over decoded packets, see [`Decoder::decode`]
+pub struct Packets<'a, I> {
+    decoder: &'a mut Decoder,
+    bytes: I,
+}
+
+impl<I: Iterator<Item = u8>> Iterator for Packets<'_, I> {
+    type Item = Result<TracePacket, DecodeError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        for byte in &mut self.bytes {
+            if let Some(packet) = self.decoder.push(byte) {
+                return Some(packet);
+            }
+        }
+        None
+    }
+}
+
+/// Parses a packet from `bytes`, returning `Ok(None)` if more bytes are needed
+fn parse(bytes: &[u8], page: u8) -> Result<Option<TracePacket>, DecodeError> {
+    let header = bytes[0];
+    let payload = &bytes[1..];
+    let malformed = DecodeError::MalformedPacket(header);
+
+    if header == 0x70 {
+        return Ok(Some(TracePacket::Overflow));
+    }
+
+    if header & 0x0f == 0x00 {
+        if header & 0x80 == 0 {
+            // local timestamp format 2: the delta is in the header
+            return Ok(Some(TracePacket::LocalTimestamp {
+                delta: u32::from(header >> 4),
+                relation: TimestampRelation::Synchronous,
+            }));
+        }
+        if header & 0xc0 != 0xc0 {
+            return Err(DecodeError::InvalidHeader(header));
+        }
+        // local timestamp format 1
+        let relation = match (header >> 4) & 0b11 {
+            0b00 => TimestampRelation::Synchronous,
+            0b01 => TimestampRelation::TimestampDelayed,
+            0b10 => TimestampRelation::PacketDelayed,
+            _ => TimestampRelation::BothDelayed,
+        };
+        return Ok(continuation(payload, 4)
+            .map_err(|_| malformed)?
+            .map(|delta| TracePacket::LocalTimestamp {
+                delta: delta as u32,
+                relation,
+            }));
+    }
+
+    if header == 0x94 {
+        return Ok(continuation(payload, 4)
+            .map_err(|_| malformed)?
+            .map(|bits| {
+                // the flags are only present in the 4th payload byte
+                let full = payload.len() == 4;
+                TracePacket::GlobalTimestamp1 {
+                    bits: (bits & 0x03ff_ffff) as u32,
+                    clock_change: full && payload[3] & (1 << 5) != 0,
+                    wrap: full && payload[3] & (1 << 6) != 0,
+                }
+            }));
+    }
+
+    if header == 0xb4 {
+        return Ok(continuation(payload, 6)
+            .map_err(|_| malformed)?
+            .map(|bits| TracePacket::GlobalTimestamp2 { bits }));
+    }
+
+    if header & 0x0b == 0x08 {
+        let hardware = header & 0x04 != 0;
+        let ex = u32::from((header >> 4) & 0b111);
+        if header & 0x80 == 0 {
+            return Ok(Some(TracePacket::Extension { info: ex, hardware }));
+        }
+        return Ok(continuation(payload, 4)
+            .map_err(|_| malformed)?
+            .map(|info| TracePacket::Extension {
+                info: ex | (info as u32) << 3,
+                hardware,
+            }));
+    }
+
+    let size = match header & 0b11 {
+        0b01 => 1,
+        0b10 => 2,
+        0b11 => 4,
+        _ => return Err(DecodeError::InvalidHeader(header)),
+    };
+    if payload.len() < size {
+        return Ok(None);
+    }
+    let mut value = [0; 4];
+    value[..size].copy_from_slice(payload);
+    let value = u32::from_le_bytes(value);
+    let address = header >> 3;
+
+    if header & 0x04 == 0 {
+        return Ok(Some(TracePacket::Instrumentation {
+            port: page * 32 + address,
+            value,
+            size: size as u8,
+        }));
+    }
+
+    hardware_packet(address, value, size)
+        .map(Some)
+        .ok_or(malformed)
+}
+
+/// Decodes a hardware source packet with discriminator `id`
+fn hardware_packet(id: u8, value: u32, size: usize) -> Option<TracePacket> {
+    let comparator = (id >> 1) & 0b11;
+    match (id, size) {
+        (0, 1) => Some(TracePacket::EventCounter(EventCounterWrap {
+            cpi: value & (1 << 0) != 0,
+            exception: value & (1 << 1) != 0,
+            sleep: value & (1 << 2) != 0,
+            lsu: value & (1 << 3) != 0,
+            fold: value & (1 << 4) != 0,
+            cyc: value & (1 << 5) != 0,
+        })),
+        (1, 2) => {
+            let action = match (value >> 12) & 0b11 {
+                0b01 => ExceptionAction::Entered,
+                0b10 => ExceptionAction::Exited,
+                0b11 => ExceptionAction::Returned,
+                _ => return None,
+            };
+            Some(TracePacket::ExceptionTrace {
+                number: (value & 0x1ff) as u16,
+                action,
+            })
+        }
+        (2, 4) => Some(TracePacket::PcSample { pc: Some(value) }),
+        (2, 1) if value == 0 => Some(TracePacket::PcSample { pc: None }),
+        (8..=15, 4) if id & 1 == 0 => Some(TracePacket::DataTracePc {
+            comparator,
+            pc: value,
+        }),
+        (8..=15, 2) if id & 1 == 1 => Some(TracePacket::DataTraceAddress {
+            comparator,
+            offset: value as u16,
+        }),
+        (16..=23, _) => Some(TracePacket::DataTraceValue {
+            comparator,
+            access: if id & 1 == 0 {
+                DataAccess::Read
+            } else {
+                DataAccess::Write
+            },
+            value,
+            size: size as u8,
+        }),
+        _ => None,
+    }
+}
+
+/// Decodes up to `max` payload bytes carrying 7 bits each, the last one having bit 7 clear
+///
+/// Returns `Ok(None)` if more bytes are needed, and `Err(())` if the payload is too long.
+fn continuation(payload: &[u8], max: usize) -> Result<Option<u64>, ()> {
+    let last = match payload.last() {
+        Some(last) => last,
+        None => return Ok(None),
+    };
+    if last & 0x80 != 0 {
+        return if payload.len() < max {
+            Ok(None)
+        } else {
+            Err(())
+        };
+    }
+    Ok(Some(
+        payload.iter().enumerate().fold(0, |value, (i, byte)| {
+            value | u64::from(byte & 0x7f) << (7 * i)
+        }),
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    extern crate std;
+
+    use std::vec::Vec;
+
+    use super::*;
+
+    fn decode_one(bytes: &[u8]) -> Result<TracePacket, DecodeError> {
+        let mut decoder = Decoder::new();
+        let mut packets = decoder.decode(bytes.iter().copied());
+        let packet = packets.next().expect("incomplete packet");
+        assert_eq!(packets.next(), None);
+        packet
+    }
+
+    #[test]
+    fn sync_and_overflow() {
+        assert_eq!(decode_one(&[0, 0, 0, 0, 0, 0, 0x80]), Ok(TracePacket::Sync));
+        assert_eq!(decode_one(&[0, 0, 0x80]), Err(DecodeError::InvalidSync));
+        assert_eq!(decode_one(&[0x70]), Ok(TracePacket::Overflow));
+    }
+
+    #[test]
+    fn instrumentation() {
+        assert_eq!(
+            decode_one(&[(5 << 3) | 0b01, b'x']),
+            Ok(TracePacket::Instrumentation {
+                port: 5,
+                value: u32::from(b'x'),
+                size: 1,
+            })
+        );
+        assert_eq!(
+            decode_one(&[(31 << 3) | 0b11, 0x78, 0x56, 0x34, 0x12]),
+            Ok(TracePacket::Instrumentation {
+                port: 31,
+                value: 0x1234_5678,
+                size: 4,
+            })
+        );
+    }
+
+    #[test]
+    fn stimulus_port_page() {
+        let mut decoder = Decoder::new();
+        // extension packet selecting page 2, then a write to port 1 of that page
+        let packets: Vec<_> = decoder.decode([0x28, (1 << 3) | 0b01, 0xaa]).collect();
+
+        assert_eq!(
+            packets,
+            [
+                Ok(TracePacket::Extension {
+                    info: 2,
+                    hardware: false,
+                }),
+                Ok(TracePacket::Instrumentation {
+                    port: 65,
+                    value: 0xaa,
+                    size: 1,
+                }),
+            ]
+        );
+    }
+
+    #[test]
+    fn local_timestamps() {
+        assert_eq!(
+            decode_one(&[0x30]),
+            Ok(TracePacket::LocalTimestamp {
+                delta: 3,
+                relation: TimestampRelation::Synchronous,
+            })
+        );
+        assert_eq!(
+            decode_one(&[0xd0, 0x81, 0x01]),
+            Ok(TracePacket::LocalTimestamp {
+                delta: 0x81,
+                relation: TimestampRelation::TimestampDelayed,
+            })
+        );
+        assert_eq!(
+            decode_one(&[0xc0, 0x80, 0x80, 0x80, 0x80]),
+            Err(DecodeError::MalformedPacket(0xc0))
+        );
+        assert_eq!(decode_one(&[0x80]), Err(DecodeError::InvalidHeader(0x80)));
+    }
+
+    #[test]
+    fn global_timestamps() {
+        assert_eq!(
+            decode_one(&[0x94, 0xff, 0xff, 0xff, 0x61]),
+            Ok(TracePacket::GlobalTimestamp1 {
+                bits: 0x003f_ffff,
+                clock_change: true,
+                wrap: true,
+            })
+        );
+        assert_eq!(
+            decode_one(&[0x94, 0x05]),
+            Ok(TracePacket::GlobalTimestamp1 {
+                bits: 5,
+                clock_change: false,
+                wrap: false,
+            })
+        );
+        assert_eq!(
+            decode_one(&[0xb4, 0x81, 0x80, 0x80, 0x01]),
+            Ok(TracePacket::GlobalTimestamp2 { bits: 1 | 1 << 21 })
+        );
+    }
+
+    #[test]
+    fn dwt_packets() {
+        assert_eq!(
+            decode_one(&[0x05, 0b10_0001]),
+            Ok(TracePacket::EventCounter(EventCounterWrap {
+                cpi: true,
+                cyc: true,
+                ..EventCounterWrap::default()
+            }))
+        );
+        // SysTick entered
+        assert_eq!(
+            decode_one(&[0x0e, 15, 0x10]),
+            Ok(TracePacket::ExceptionTrace {
+                number: 15,
+                action: ExceptionAction::Entered,
+            })
+        );
+        assert_eq!(
+            decode_one(&[0x0e, 15, 0x00]),
+            Err(DecodeError::MalformedPacket(0x0e))
+        );
+        assert_eq!(
+            decode_one(&[0x17, 0x00, 0x01, 0x00, 0x08]),
+            Ok(TracePacket::PcSample {
+                pc: Some(0x0800_0100)
+            })
+        );
+        assert_eq!(
+            decode_one(&[0x15, 0x00]),
+            Ok(TracePacket::PcSample { pc: None })
+        );
+        // comparator 1 PC value, then data address offset
+        assert_eq!(
+            decode_one(&[0x57, 0x00, 0x01, 0x00, 0x08]),
+            Ok(TracePacket::DataTracePc {
+                comparator: 1,
+                pc: 0x0800_0100,
+            })
+        );
+        assert_eq!(
+            decode_one(&[0x5e, 0x34, 0x12]),
+            Ok(TracePacket::DataTraceAddress {
+                comparator: 1,
+                offset: 0x1234,
+            })
+        );
+        // comparator 2 write of a halfword
+        assert_eq!(
+            decode_one(&[0xae, 0xcd, 0xab]),
+            Ok(TracePacket::DataTraceValue {
+                comparator: 2,
+                access: DataAccess::Write,
+                value: 0xabcd,
+                size: 2,
+            })
+        );
+    }
+
+    #[test]
+    fn packets_split_across_reads() {
+        let mut decoder = Decoder::new();
+
+        assert_eq!(decoder.decode([0x03, 0x01, 0x02]).next(), None);
+        assert_eq!(
+            decoder.decode([0x03, 0x04, 0x70]).collect::<Vec<_>>(),
+            [
+                Ok(TracePacket::Instrumentation {
+                    port: 0,
+                    value: 0x0403_0201,
+                    size: 4,
+                }),
+                Ok(TracePacket::Overflow),
+            ]
+        );
+    }
+}
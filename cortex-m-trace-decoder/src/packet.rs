@@ -0,0 +1,136 @@
+//! Trace packets
+
+/// A decoded ITM or DWT trace packet
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum TracePacket {
+    /// Synchronization packet, marking a packet boundary
+    Sync,
+    /// Packets were lost because the ITM or DWT output FIFO overflowed
+    Overflow,
+    /// Data written to an ITM stimulus port, e.g. by `iprintln!`
+    Instrumentation {
+        /// Stimulus port number
+        port: u8,
+        /// Payload, zero-extended
+        value: u32,
+        /// Size of the write in bytes: 1, 2 or 4
+        size: u8,
+    },
+    /// Local timestamp: time elapsed since the previous local timestamp
+    LocalTimestamp {
+        /// Elapsed timestamp clock cycles, divided by the prescaler
+        delta: u32,
+        /// Timing of this timestamp relative to the packet it refers to
+        relation: TimestampRelation,
+    },
+    /// Global timestamp, low order bits
+    GlobalTimestamp1 {
+        /// Bits [25:0] of the global timestamp; bits not sent are zero
+        bits: u32,
+        /// The global timestamp clock changed since the last global timestamp
+        clock_change: bool,
+        /// The high order bits changed since the last `GlobalTimestamp2` packet
+        wrap: bool,
+    },
+    /// Global timestamp, high order bits
+    GlobalTimestamp2 {
+        /// Bits [63:26] of the global timestamp, shifted down by 26
+        bits: u64,
+    },
+    /// Extension packet
+    Extension {
+        /// The extension information, e.g. the page of the following stimulus ports
+        info: u32,
+        /// `true` for hardware source information, `false` for stimulus port paging
+        hardware: bool,
+    },
+    /// DWT profiling counters that wrapped around
+    EventCounter(EventCounterWrap),
+    /// Exception entry, exit or return
+    ExceptionTrace {
+        /// Exception number
+        number: u16,
+        /// What happened to the exception
+        action: ExceptionAction,
+    },
+    /// Periodic PC sample
+    PcSample {
+        /// Sampled program counter, or `None` if the processor was sleeping
+        pc: Option<u32>,
+    },
+    /// Program counter of the instruction that matched a DWT comparator
+    DataTracePc {
+        /// DWT comparator number
+        comparator: u8,
+        /// Program counter
+        pc: u32,
+    },
+    /// Low order bits of the data address that matched a DWT comparator
+    DataTraceAddress {
+        /// DWT comparator number
+        comparator: u8,
+        /// Bits [15:0] of the data address
+        offset: u16,
+    },
+    /// Data value that matched a DWT comparator
+    DataTraceValue {
+        /// DWT comparator number
+        comparator: u8,
+        /// Kind of access
+        access: DataAccess,
+        /// Value read or written, zero-extended
+        value: u32,
+        /// Size of the access in bytes: 1, 2 or 4
+        size: u8,
+    },
+}
+
+/// Timing of a local timestamp relative to the packet it refers to (TC field)
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum TimestampRelation {
+    /// The timestamp is synchronous to the packet
+    Synchronous,
+    /// The timestamp was delayed relative to the packet
+    TimestampDelayed,
+    /// The packet was delayed relative to the event it reports
+    PacketDelayed,
+    /// Both the timestamp and the packet were delayed
+    BothDelayed,
+}
+
+/// DWT profiling counters that wrapped around, as reported by an event counter packet
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct EventCounterWrap {
+    /// `DWT::cpicnt` wrapped around
+    pub cpi: bool,
+    /// `DWT::exccnt` wrapped around
+    pub exception: bool,
+    /// `DWT::sleepcnt` wrapped around
+    pub sleep: bool,
+    /// `DWT::lsucnt` wrapped around
+    pub lsu: bool,
+    /// `DWT::foldcnt` wrapped around
+    pub fold: bool,
+    /// `DWT::cyccnt` bit 6 or bit 10 toggled, depending on `DWT::ctrl` CYCTAP
+    pub cyc: bool,
+}
+
+/// What happened to an exception, as reported by an exception trace packet
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ExceptionAction {
+    /// The exception was entered
+    Entered,
+    /// The exception was exited
+    Exited,
+    /// Execution returned to the exception
+    Returned,
+}
+
+/// Kind of a data access matched by a DWT comparator
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum DataAccess {
+    /// Data read
+    Read,
+    /// Data write
+    Write,
+}
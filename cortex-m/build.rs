@@ -1,4 +1,6 @@
 use std::env;
+use std::fs;
+use std::path::PathBuf;
 
 fn main() {
     let target = env::var("TARGET").unwrap();
@@ -41,4 +43,40 @@ fn main() {
     if target.ends_with("-eabihf") {
         println!("cargo:rustc-cfg=has_fpu");
     }
+
+    let has_basepri = target.starts_with("thumbv7m-")
+        || target.starts_with("thumbv7em-")
+        || target.starts_with("thumbv8m.main");
+    if env::var_os("CARGO_FEATURE_CRITICAL_SECTION_BASEPRI").is_some() && has_basepri {
+        basepri_threshold();
+    }
+}
+
+/// Writes the BASEPRI threshold of the `critical-section-basepri` implementation
+fn basepri_threshold() {
+    const VAR: &str = "CORTEX_M_CS_BASEPRI_THRESHOLD";
+    println!("cargo:rerun-if-env-changed={VAR}");
+
+    let value = env::var(VAR).unwrap_or_else(|_| {
+        panic!(
+            "the `critical-section-basepri` feature requires the {VAR} environment variable, \
+             the hardware priority to which critical sections raise BASEPRI"
+        )
+    });
+    let value = value.trim();
+    let threshold = match value.strip_prefix("0x") {
+        Some(hex) => u8::from_str_radix(hex, 16),
+        None => value.parse(),
+    };
+    let threshold = match threshold {
+        Ok(threshold) if threshold != 0 => threshold,
+        _ => panic!("{VAR} must be a hardware priority in range 1..=255, got `{value}`"),
+    };
+
+    let out = PathBuf::from(env::var_os("OUT_DIR").unwrap());
+    fs::write(
+        out.join("basepri_threshold.rs"),
+        format!("const THRESHOLD: u8 = {threshold:#04x};\n"),
+    )
+    .unwrap();
 }
@@ -14,10 +14,20 @@ unsafe impl Impl for SingleCoreCriticalSection {
         let restore_state = primask::read_raw();
         // NOTE: Fence guarantees are provided by interrupt::disable(), which performs a `compiler_fence(SeqCst)`.
         interrupt::disable();
+        #[cfg(feature = "critical-section-timing")]
+        if restore_state & 1 == 0 {
+            // NOTE(unsafe) interrupts are disabled
+            unsafe { crate::critical_section_timing::enter() };
+        }
         restore_state
     }
 
     unsafe fn release(restore_state: RawRestoreState) {
+        #[cfg(feature = "critical-section-timing")]
+        if restore_state & 1 == 0 {
+            // NOTE(unsafe) interrupts are still disabled
+            unsafe { crate::critical_section_timing::exit(None) };
+        }
         // NOTE: Fence guarantees are provided by primask::write_raw(), which performs a `compiler_fence(SeqCst)`.
         unsafe { primask::write_raw(restore_state) };
     }
@@ -0,0 +1,32 @@
+use core::sync::atomic::{Ordering, compiler_fence};
+
+use critical_section::{Impl, RawRestoreState, set_impl};
+
+use crate::register::{basepri, basepri_max};
+
+// Defines `THRESHOLD`, from the `CORTEX_M_CS_BASEPRI_THRESHOLD` environment variable
+include!(concat!(env!("OUT_DIR"), "/basepri_threshold.rs"));
+
+struct BasepriCriticalSection;
+set_impl!(BasepriCriticalSection);
+
+unsafe impl Impl for BasepriCriticalSection {
+    unsafe fn acquire() -> RawRestoreState {
+        let restore_state = basepri::read();
+        // `basepri_max` only raises BASEPRI, so nested critical sections never lower it.
+        basepri_max::write(THRESHOLD);
+        debug_assert!(
+            basepri::read() != 0,
+            "CORTEX_M_CS_BASEPRI_THRESHOLD is not representable with the implemented priority bits"
+        );
+        // Ensure no subsequent memory accesses are reordered to before BASEPRI is raised.
+        compiler_fence(Ordering::SeqCst);
+        restore_state
+    }
+
+    unsafe fn release(restore_state: RawRestoreState) {
+        // Ensure no preceeding memory accesses are reordered to after BASEPRI is restored.
+        compiler_fence(Ordering::SeqCst);
+        unsafe { basepri::write(restore_state) };
+    }
+}
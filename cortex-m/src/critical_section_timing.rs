@@ -0,0 +1,126 @@
+//! Critical section duration statistics
+//!
+//! With the `critical-section-timing` feature, [`interrupt::free`] and the
+//! `critical-section-single-core` implementation timestamp the entry and exit of every outermost
+//! critical section with the DWT cycle counter, and record how long interrupts were masked.
+//! [`interrupt::free`] is `#[track_caller]` with this feature, so the location of its caller is
+//! recorded together with the longest critical section.
+//!
+//! The cycle counter must be enabled, see `DWT::enable_cycle_counter`, or every critical section
+//! is recorded as 0 cycles long. Critical sections longer than `2^32` cycles are not measured
+//! correctly.
+//!
+//! # Examples
+//!
+//! ``` no_run
+//! use cortex_m::critical_section_timing;
+//!
+//! let stats = critical_section_timing::take();
+//! if let Some(location) = stats.max_location {
+//!     // report `stats.max_cycles` and `location`
+//! }
+//! ```
+//!
+//! [`interrupt::free`]: crate::interrupt::free
+
+use core::cell::UnsafeCell;
+use core::panic::Location;
+
+use crate::peripheral::DWT;
+use crate::{interrupt, register};
+
+/// Statistics of the critical sections, in cycles
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Stats {
+    /// Number of critical sections
+    pub count: u32,
+    /// Total number of cycles spent in critical sections
+    pub total_cycles: u64,
+    /// Duration of the longest critical section
+    pub max_cycles: u32,
+    /// Location of the `interrupt::free` call of the longest critical section, or `None` if it
+    /// was entered through the `critical-section` crate
+    pub max_location: Option<&'static Location<'static>>,
+}
+
+impl Stats {
+    const fn new() -> Self {
+        Stats {
+            count: 0,
+            total_cycles: 0,
+            max_cycles: 0,
+            max_location: None,
+        }
+    }
+}
+
+struct State {
+    /// Cycle count when the current critical section was entered
+    entry: UnsafeCell<u32>,
+    stats: UnsafeCell<Stats>,
+}
+
+// NOTE(unsafe) only accessed with interrupts disabled, on single core systems
+unsafe impl Sync for State {}
+
+static STATE: State = State {
+    entry: UnsafeCell::new(0),
+    stats: UnsafeCell::new(Stats::new()),
+};
+
+/// Returns the statistics of the critical sections so far
+#[inline]
+pub fn stats() -> Stats {
+    with_stats(|stats| *stats)
+}
+
+/// Returns the statistics of the critical sections so far, and resets them
+#[inline]
+pub fn take() -> Stats {
+    with_stats(|stats| core::mem::replace(stats, Stats::new()))
+}
+
+/// Runs `f` on the statistics with interrupts disabled
+///
+/// This does not use `interrupt::free`, so reading the statistics is not recorded as a critical
+/// section of its own.
+#[inline]
+fn with_stats<R>(f: impl FnOnce(&mut Stats) -> R) -> R {
+    let primask = register::primask::read_raw();
+    interrupt::disable();
+
+    // NOTE(unsafe) interrupts are disabled
+    let r = f(unsafe { &mut *STATE.stats.get() });
+
+    // NOTE(unsafe) restores the state of PRIMASK on entry
+    unsafe { register::primask::write_raw(primask) };
+    r
+}
+
+/// Records the entry of an outermost critical section
+///
+/// # Safety
+///
+/// Interrupts must be disabled.
+#[inline(always)]
+pub(crate) unsafe fn enter() {
+    unsafe { *STATE.entry.get() = DWT::cycle_count() };
+}
+
+/// Records the exit of the critical section entered by the last call to `enter`
+///
+/// # Safety
+///
+/// Interrupts must still be disabled.
+#[inline(always)]
+pub(crate) unsafe fn exit(location: Option<&'static Location<'static>>) {
+    let cycles = DWT::cycle_count().wrapping_sub(unsafe { *STATE.entry.get() });
+
+    let stats = unsafe { &mut *STATE.stats.get() };
+    stats.count = stats.count.wrapping_add(1);
+    stats.total_cycles += u64::from(cycles);
+    if cycles >= stats.max_cycles {
+        stats.max_cycles = cycles;
+        stats.max_location = location;
+    }
+}
@@ -0,0 +1,149 @@
+//! Self-hosted debugging with the `DebugMonitor` exception
+//!
+//! In monitor mode, debug events raise the `DebugMonitor` exception instead of halting the
+//! processor, which lets firmware debug itself: breakpoints set with the FPB or the `BKPT`
+//! instruction, DWT watchpoints and single-stepping are all handled by the `DebugMonitor`
+//! handler. Monitor mode is enabled with [`DCB::enable_debug_monitor`](crate::peripheral::DCB::enable_debug_monitor).
+//!
+//! The `DebugMonitor` handler calls [`take_event`] to find out why it was entered. To single-step
+//! the interrupted code, the handler calls [`step`] and returns: the processor executes one
+//! instruction and enters `DebugMonitor` again with a [`DebugEvent::Step`]. [`resume`] returns to
+//! normal execution.
+//!
+//! **NOTE** This module is not available on ARMv6-M and ARMv8-M Baseline, which have no
+//! `DebugMonitor` exception.
+//!
+//! # Limitations
+//!
+//! - Debug events halt the processor instead while a debugger has enabled halting debug.
+//! - Debug events are ignored while executing at a priority equal to or higher than the one of
+//!   `DebugMonitor`, except `BKPT` instructions which escalate to `HardFault`.
+//! - The stacked PC of a breakpoint event points at the breakpointed instruction. For a `BKPT`
+//!   instruction the handler must advance the stacked PC by 2, or execution will hit it again.
+//! - The `DebugMonitor` handler is not in Debug state, so it can not transfer core registers
+//!   through DCRSR and DCRDR. The registers of the interrupted code are in the exception frame,
+//!   and `r4`-`r11` in the `CalleeSavedRegisters` saved by the `cortex-m-rt` trampoline.
+//!
+//! # Examples
+//!
+//! With `cortex-m-rt`, the `DebugMonitor` handler can receive the stacked exception frame, and
+//! the callee-saved registers:
+//!
+//! ``` ignore
+//! use cortex_m::debug_monitor::{self, DebugEvent};
+//!
+//! #[exception]
+//! fn DebugMonitor(frame: &mut ExceptionFrame, registers: &mut CalleeSavedRegisters) {
+//!     match debug_monitor::take_event() {
+//!         Some(DebugEvent::Breakpoint) => {
+//!             if unsafe { debug_monitor::bkpt_immediate(frame.pc()) }.is_some() {
+//!                 // skip the `BKPT` instruction
+//!                 unsafe { frame.set_pc(frame.pc().wrapping_add(2)) };
+//!             }
+//!             // execute the next instruction, then come back here
+//!             debug_monitor::step();
+//!         }
+//!         Some(DebugEvent::Step) => {
+//!             hprintln!("stepped to {:#010x}, r7 = {:#010x}", frame.pc(), registers.r7());
+//!             debug_monitor::resume();
+//!         }
+//!         _ => debug_monitor::resume(),
+//!     }
+//! }
+//! ```
+
+use crate::interrupt;
+use crate::peripheral::scb::DebugFaultStatus;
+use crate::peripheral::{DCB, SCB};
+
+/// Reason for entering the `DebugMonitor` exception
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum DebugEvent {
+    /// A `BKPT` instruction or an FPB breakpoint
+    Breakpoint,
+    /// A DWT watchpoint
+    Watchpoint,
+    /// Completion of a single-step
+    Step,
+    /// The external debug request signal
+    External,
+    /// A vector catch
+    VectorCatch,
+}
+
+impl DebugEvent {
+    /// Decodes the most relevant event recorded in `status`
+    ///
+    /// A watchpoint takes precedence over a breakpoint, which takes precedence over a step, as a
+    /// single-step may complete on an instruction that also triggered a breakpoint or watchpoint.
+    #[inline]
+    pub fn from_status(status: DebugFaultStatus) -> Option<Self> {
+        if status.watchpoint {
+            Some(DebugEvent::Watchpoint)
+        } else if status.breakpoint {
+            Some(DebugEvent::Breakpoint)
+        } else if status.halted {
+            Some(DebugEvent::Step)
+        } else if status.external {
+            Some(DebugEvent::External)
+        } else if status.vector_catch {
+            Some(DebugEvent::VectorCatch)
+        } else {
+            None
+        }
+    }
+}
+
+/// Returns the reason for entering the `DebugMonitor` exception, and clears it
+///
+/// Returns `None` when no debug event was recorded, e.g. when `DebugMonitor` was pended with
+/// [`DCB::pend_debug_monitor`].
+#[inline]
+pub fn take_event() -> Option<DebugEvent> {
+    let status = SCB::debug_fault_status();
+    SCB::clear_debug_fault_status(status);
+    DebugEvent::from_status(status)
+}
+
+/// Single-steps the interrupted code
+///
+/// Once the `DebugMonitor` handler returns, the processor executes one instruction and enters
+/// `DebugMonitor` again with a [`DebugEvent::Step`].
+#[inline]
+pub fn step() {
+    // NOTE(unsafe) DEMCR is modified in a critical section
+    interrupt::free(|_| unsafe { DCB::modify_monitor_step(true) });
+}
+
+/// Stops single-stepping: the interrupted code runs normally once the `DebugMonitor` handler
+/// returns
+#[inline]
+pub fn resume() {
+    // NOTE(unsafe) DEMCR is modified in a critical section
+    interrupt::free(|_| unsafe { DCB::modify_monitor_step(false) });
+}
+
+/// Returns `true` if the interrupted code is being single-stepped
+#[inline]
+pub fn is_stepping() -> bool {
+    DCB::is_monitor_step_enabled()
+}
+
+/// Returns the immediate of the `BKPT` instruction at `pc`, if any
+///
+/// Use this on [`DebugEvent::Breakpoint`] to tell a `BKPT` instruction, which must be skipped,
+/// apart from an FPB breakpoint.
+///
+/// # Safety
+///
+/// `pc` must be the address of a readable, halfword aligned instruction, such as the stacked PC of
+/// the exception frame.
+#[inline]
+pub unsafe fn bkpt_immediate(pc: u32) -> Option<u8> {
+    let instruction = unsafe { core::ptr::read_volatile(pc as *const u16) };
+    if instruction & 0xFF00 == 0xBE00 {
+        Some(instruction as u8)
+    } else {
+        None
+    }
+}
@@ -0,0 +1,301 @@
+//! Deferred work run from the PendSV exception
+//!
+//! Interrupt handlers should return quickly, and leave the longer processing of an event to a
+//! "bottom half" running at a lower priority. [`defer`] queues a callback, a function pointer
+//! and a `usize` argument, and pends PendSV, whose handler then runs the queued callbacks with
+//! [`run_pending`], in the order they were queued.
+//!
+//! [`deferred_pendsv_handler!`](crate::deferred_pendsv_handler) defines that handler. PendSV
+//! must be given the lowest priority, so callbacks run once no interrupt handler is running:
+//!
+//! ``` no_run
+//! use cortex_m::peripheral::scb::SystemHandler;
+//!
+//! let mut p = cortex_m::Peripherals::take().unwrap();
+//! // NOTE(unsafe) PendSV is not used by anything else
+//! unsafe { p.SCB.set_priority(SystemHandler::PendSV, 0xff) };
+//!
+//! cortex_m::deferred_pendsv_handler!();
+//!
+//! fn uart_bottom_half(bytes: usize) {
+//!     // process the received bytes, with interrupts enabled
+//! }
+//!
+//! fn uart_interrupt_handler() {
+//!     let bytes = 4;
+//!     cortex_m::deferred::defer(uart_bottom_half, bytes).ok();
+//! }
+//! ```
+//!
+//! The queues are protected by short critical sections, so callbacks can be queued from any
+//! context, including other callbacks. They must only be used on single core systems.
+
+use core::cell::{Cell, RefCell};
+
+use crate::interrupt::{self, CriticalSection, Mutex};
+use crate::peripheral::SCB;
+
+/// A deferred callback, called with the argument it was queued with
+pub type Callback = fn(usize);
+
+/// Capacity of the queue used by [`defer`]
+pub const CAPACITY: usize = 32;
+
+static QUEUE: DeferredQueue<CAPACITY> = DeferredQueue::new();
+
+/// The queue is full
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct QueueFull;
+
+/// Queues `callback`, to be called with `arg` by the PendSV handler, and pends PendSV
+///
+/// Returns `Err(QueueFull)`, and drops the callback, if [`CAPACITY`] callbacks are already
+/// waiting.
+#[inline]
+pub fn defer(callback: Callback, arg: usize) -> Result<(), QueueFull> {
+    QUEUE.defer(callback, arg)
+}
+
+/// Runs the callbacks queued with [`defer`], until the queue is empty
+///
+/// This is called by the handler defined by
+/// [`deferred_pendsv_handler!`](crate::deferred_pendsv_handler).
+#[inline]
+pub fn run_pending() {
+    QUEUE.run_pending()
+}
+
+/// Returns the number of callbacks dropped by [`defer`] because the queue was full
+#[inline]
+pub fn dropped() -> usize {
+    QUEUE.dropped()
+}
+
+/// A queue of up to `N` deferred callbacks, run from the PendSV handler
+///
+/// [`defer`] and [`run_pending`] use a queue of [`CAPACITY`] callbacks. A queue of another size can
+/// be declared as a `static`, and run by passing it to
+/// [`deferred_pendsv_handler!`](crate::deferred_pendsv_handler).
+pub struct DeferredQueue<const N: usize> {
+    callbacks: Mutex<RefCell<CallbackQueue<N>>>,
+    dropped: Mutex<Cell<usize>>,
+}
+
+impl<const N: usize> DeferredQueue<N> {
+    /// Creates an empty queue
+    #[inline]
+    pub const fn new() -> Self {
+        DeferredQueue {
+            callbacks: Mutex::new(RefCell::new(CallbackQueue::new())),
+            dropped: Mutex::new(Cell::new(0)),
+        }
+    }
+
+    /// Queues `callback`, to be called with `arg` by the PendSV handler, and pends PendSV
+    ///
+    /// Returns `Err(QueueFull)`, and drops the callback, if `N` callbacks are already waiting.
+    #[inline]
+    pub fn defer(&self, callback: Callback, arg: usize) -> Result<(), QueueFull> {
+        interrupt::free(|cs| self.push(cs, callback, arg))?;
+        SCB::set_pendsv();
+        Ok(())
+    }
+
+    /// Runs the queued callbacks, until the queue is empty
+    ///
+    /// The callbacks run with interrupts enabled. Callbacks queued meanwhile, e.g. by interrupt
+    /// handlers or by the callbacks themselves, also run before this returns.
+    #[inline]
+    pub fn run_pending(&self) {
+        while let Some((callback, arg)) =
+            interrupt::free(|cs| self.callbacks.borrow(cs).borrow_mut().pop())
+        {
+            callback(arg);
+        }
+    }
+
+    /// Returns the number of callbacks waiting to run
+    #[inline]
+    pub fn len(&self) -> usize {
+        interrupt::free(|cs| self.callbacks.borrow(cs).borrow().len())
+    }
+
+    /// Returns `true` if no callback is waiting to run
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the number of callbacks dropped because the queue was full
+    #[inline]
+    pub fn dropped(&self) -> usize {
+        interrupt::free(|cs| self.dropped.borrow(cs).get())
+    }
+
+    /// Queues a callback, counting it as dropped if the queue is full
+    fn push(&self, cs: &CriticalSection, callback: Callback, arg: usize) -> Result<(), QueueFull> {
+        let queued = self.callbacks.borrow(cs).borrow_mut().push(callback, arg);
+        if queued.is_err() {
+            let dropped = self.dropped.borrow(cs);
+            dropped.set(dropped.get().wrapping_add(1));
+        }
+        queued
+    }
+}
+
+impl<const N: usize> Default for DeferredQueue<N> {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A bounded FIFO queue of up to `N` callbacks and their arguments
+///
+/// This is the storage of [`DeferredQueue`]. It is not interrupt-safe by itself: share it
+/// between contexts in a [`Mutex`], like `cortex-m-dispatcher` does for its task queues.
+///
+/// # Examples
+///
+/// ```
+/// use cortex_m::deferred::{CallbackQueue, QueueFull};
+///
+/// fn callback(_: usize) {}
+///
+/// let mut queue = CallbackQueue::<2>::new();
+/// queue.push(callback, 1).unwrap();
+/// queue.push(callback, 2).unwrap();
+/// assert_eq!(queue.push(callback, 3), Err(QueueFull));
+/// assert_eq!(queue.pop().map(|(_, arg)| arg), Some(1));
+/// ```
+pub struct CallbackQueue<const N: usize> {
+    callbacks: [(Callback, usize); N],
+    /// Index of the oldest callback
+    head: usize,
+    len: usize,
+}
+
+impl<const N: usize> CallbackQueue<N> {
+    /// Creates an empty queue
+    #[inline]
+    pub const fn new() -> Self {
+        const { assert!(N != 0, "the queue must have room for at least one callback") };
+
+        CallbackQueue {
+            callbacks: [(nop, 0); N],
+            head: 0,
+            len: 0,
+        }
+    }
+
+    /// Adds `callback` and its argument `arg` at the end of the queue
+    ///
+    /// Returns `Err(QueueFull)` if `N` callbacks are already queued.
+    #[inline]
+    pub fn push(&mut self, callback: Callback, arg: usize) -> Result<(), QueueFull> {
+        if self.len == N {
+            return Err(QueueFull);
+        }
+        self.callbacks[(self.head + self.len) % N] = (callback, arg);
+        self.len += 1;
+        Ok(())
+    }
+
+    /// Removes the oldest callback and its argument from the queue
+    #[inline]
+    pub fn pop(&mut self) -> Option<(Callback, usize)> {
+        if self.len == 0 {
+            return None;
+        }
+        let callback = self.callbacks[self.head];
+        self.head = (self.head + 1) % N;
+        self.len -= 1;
+        Some(callback)
+    }
+
+    /// Returns the number of queued callbacks
+    #[inline]
+    pub const fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if no callback is queued
+    #[inline]
+    pub const fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+impl<const N: usize> Default for CallbackQueue<N> {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn nop(_: usize) {}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn callback(_: usize) {}
+
+    fn args<const N: usize>(queue: &mut CallbackQueue<N>) -> [Option<usize>; N] {
+        core::array::from_fn(|_| queue.pop().map(|(_, arg)| arg))
+    }
+
+    #[test]
+    fn callback_queue_is_fifo() {
+        let mut queue = CallbackQueue::<3>::new();
+        assert!(queue.is_empty());
+        assert!(queue.pop().is_none());
+
+        queue.push(callback, 1).unwrap();
+        queue.push(callback, 2).unwrap();
+        assert_eq!(queue.len(), 2);
+        assert_eq!(args(&mut queue), [Some(1), Some(2), None]);
+    }
+
+    #[test]
+    fn callback_queue_wraps_around() {
+        let mut queue = CallbackQueue::<3>::new();
+        queue.push(callback, 1).unwrap();
+        queue.push(callback, 2).unwrap();
+        assert_eq!(queue.pop().map(|(_, arg)| arg), Some(1));
+        assert_eq!(queue.pop().map(|(_, arg)| arg), Some(2));
+
+        // the head is now at the last slot
+        queue.push(callback, 3).unwrap();
+        queue.push(callback, 4).unwrap();
+        queue.push(callback, 5).unwrap();
+        assert_eq!(args(&mut queue), [Some(3), Some(4), Some(5)]);
+    }
+
+    #[test]
+    fn callback_queue_rejects_when_full() {
+        let mut queue = CallbackQueue::<2>::new();
+        queue.push(callback, 1).unwrap();
+        queue.push(callback, 2).unwrap();
+        assert_eq!(queue.push(callback, 3), Err(QueueFull));
+        assert_eq!(queue.len(), 2);
+
+        // a rejected callback leaves the queue unchanged
+        assert_eq!(args(&mut queue), [Some(1), Some(2)]);
+    }
+
+    #[test]
+    fn deferred_queue_counts_dropped_callbacks() {
+        let queue = DeferredQueue::<2>::new();
+        // NOTE(unsafe) single threaded test, the queue is not shared
+        let cs = unsafe { CriticalSection::new() };
+
+        assert_eq!(queue.push(&cs, callback, 1), Ok(()));
+        assert_eq!(queue.push(&cs, callback, 2), Ok(()));
+        assert_eq!(queue.push(&cs, callback, 3), Err(QueueFull));
+        assert_eq!(queue.push(&cs, callback, 4), Err(QueueFull));
+
+        assert_eq!(queue.dropped.borrow(&cs).get(), 2);
+        assert_eq!(queue.callbacks.borrow(&cs).borrow().len(), 2);
+    }
+}
@@ -3,6 +3,7 @@
 pub use bare_metal::{CriticalSection, Mutex, Nr};
 #[cfg(cortex_m)]
 use core::arch::asm;
+use core::cell::UnsafeCell;
 #[cfg(cortex_m)]
 use core::sync::atomic::{Ordering, compiler_fence};
 use cortex_m_macros::asm_cfg;
@@ -64,7 +65,11 @@ pub unsafe fn enable() {
 /// Execute closure `f` in an interrupt-free context.
 ///
 /// This as also known as a "critical section".
+///
+/// With the `critical-section-timing` feature, the duration of the critical section and the
+/// location of the caller are recorded, see [`critical_section_timing`](crate::critical_section_timing).
 #[cfg(cortex_m)]
+#[cfg_attr(feature = "critical-section-timing", track_caller)]
 #[inline]
 pub fn free<F, R>(f: F) -> R
 where
@@ -78,8 +83,25 @@ where
     // disable interrupts
     disable();
 
+    // only the outermost critical section is timed
+    #[cfg(feature = "critical-section-timing")]
+    let outermost = primask & 1 == 0;
+    #[cfg(feature = "critical-section-timing")]
+    if outermost {
+        // NOTE(unsafe) interrupts are disabled
+        unsafe { crate::critical_section_timing::enter() };
+    }
+
     let r = f(&unsafe { CriticalSection::new() });
 
+    #[cfg(feature = "critical-section-timing")]
+    if outermost {
+        // NOTE(unsafe) interrupts are still disabled
+        unsafe {
+            crate::critical_section_timing::exit(Some(core::panic::Location::caller()));
+        }
+    }
+
     unsafe {
         crate::register::primask::write_raw(primask);
     }
@@ -100,3 +122,130 @@ where
 {
     panic!("cortex_m::interrupt::free() is only functional on cortex-m platforms");
 }
+
+/// Runs `f` with BASEPRI raised to `ceiling`, then restores BASEPRI
+///
+/// `ceiling` must not be 0, which would leave all interrupts unmasked.
+///
+/// # Panics
+///
+/// Panics if BASEPRI reads back as 0, i.e. if `ceiling` only sets priority bits that are not
+/// implemented by the device.
+#[cfg(any(armv7m, armv8m_main))]
+#[inline]
+pub(crate) fn with_basepri<R>(ceiling: u8, f: impl FnOnce() -> R) -> R {
+    let basepri = crate::register::basepri::read();
+    crate::register::basepri_max::write(ceiling);
+    // the low priority bits are not implemented: e.g. `0x08` reads back as 0 on a device with 4
+    // priority bits, which masks nothing
+    assert!(
+        crate::register::basepri::read() != 0,
+        "the BASEPRI ceiling {:#04x} is not representable with the implemented priority bits",
+        ceiling
+    );
+    // Ensure no subsequent memory accesses are reordered to before BASEPRI is raised.
+    compiler_fence(Ordering::SeqCst);
+
+    let r = f();
+
+    // Ensure no preceeding memory accesses are reordered to after BASEPRI is restored.
+    compiler_fence(Ordering::SeqCst);
+    // NOTE(unsafe) restores the BASEPRI value read above
+    unsafe { crate::register::basepri::write(basepri) };
+    r
+}
+
+/// A mutex protected by a priority ceiling
+///
+/// [`lock`](CeilingMutex::lock) raises BASEPRI to the hardware priority `CEILING`, masking only
+/// the interrupts that may access the resource, instead of all interrupts like [`free`].
+/// Interrupts more urgent than the ceiling keep their latency. See
+/// [`Priority::to_hw`](crate::priority::Priority::to_hw) to compute the ceiling from a logical
+/// level.
+///
+/// On ARMv6-M and ARMv8-M Baseline, which do not implement BASEPRI, and with a `CEILING` of 0,
+/// the highest priority, `lock` masks all interrupts with PRIMASK instead. On Cortex-M7 r0p1,
+/// the `cm7-r0p1` feature must be enabled, see [`register::basepri`](crate::register::basepri).
+///
+/// # Examples
+///
+/// ``` no_run
+/// use cortex_m::interrupt::CeilingMutex;
+/// use cortex_m::priority::Priority;
+///
+/// type Prio = Priority<4>;
+///
+/// // shared by handlers of logical levels 1 and 2; handlers of level 3 and above are not masked
+/// static COUNTER: CeilingMutex<u32, { Prio::new::<2>().to_hw() }> =
+///     unsafe { CeilingMutex::new(0) };
+///
+/// COUNTER.lock(|counter| *counter += 1);
+/// ```
+pub struct CeilingMutex<T, const CEILING: u8> {
+    locked: UnsafeCell<bool>,
+    inner: UnsafeCell<T>,
+}
+
+// NOTE(unsafe) the inner value is only accessed with the interrupts of its users masked
+unsafe impl<T: Send, const CEILING: u8> Sync for CeilingMutex<T, CEILING> {}
+
+impl<T, const CEILING: u8> CeilingMutex<T, CEILING> {
+    /// Creates a new mutex holding `value`
+    ///
+    /// # Safety
+    ///
+    /// The mutex must only be locked from thread mode and from exception handlers whose
+    /// priority is equal to or lower than `CEILING`, i.e. whose hardware priority value is
+    /// greater than or equal to `CEILING`.
+    ///
+    /// `CEILING` must be representable with the priority bits implemented by the device, i.e.
+    /// its low, unimplemented bits must be 0, as computed by
+    /// [`Priority::to_hw`](crate::priority::Priority::to_hw). `lock` panics if the ceiling
+    /// reads back from BASEPRI as 0.
+    #[inline]
+    pub const unsafe fn new(value: T) -> Self {
+        CeilingMutex {
+            locked: UnsafeCell::new(false),
+            inner: UnsafeCell::new(value),
+        }
+    }
+
+    /// Runs `f` with exclusive access to the value, masking the interrupts up to the ceiling
+    ///
+    /// # Panics
+    ///
+    /// Panics if the mutex is already locked by the caller, i.e. when `lock` is nested, or if
+    /// `CEILING` is not representable with the implemented priority bits.
+    #[inline]
+    pub fn lock<R>(&self, f: impl FnOnce(&mut T) -> R) -> R {
+        let run = || {
+            // NOTE(unsafe) no other user of the mutex runs while the interrupts up to the
+            // ceiling are masked
+            let locked = unsafe { &mut *self.locked.get() };
+            assert!(!*locked, "CeilingMutex locked recursively");
+            *locked = true;
+            let r = f(unsafe { &mut *self.inner.get() });
+            *locked = false;
+            r
+        };
+
+        #[cfg(any(armv7m, armv8m_main))]
+        if CEILING != 0 {
+            return with_basepri(CEILING, run);
+        }
+
+        free(|_| run())
+    }
+
+    /// Returns a mutable reference to the value, without locking
+    #[inline]
+    pub fn get_mut(&mut self) -> &mut T {
+        self.inner.get_mut()
+    }
+
+    /// Consumes the mutex, returning the value
+    #[inline]
+    pub fn into_inner(self) -> T {
+        self.inner.into_inner()
+    }
+}
@@ -1,40 +1,135 @@
 //! Instrumentation Trace Macrocell
 //!
 //! **NOTE** This module is only available on ARMv7-M and newer.
+//!
+//! The `write_*` functions wait for the stimulus port FIFO for as long as needed. When no trace
+//! probe drains the ITM output, they block forever. The `try_write_*` functions instead drop their
+//! output when the stimulus port is disabled, and give up once a [`Timeout`] expires, returning
+//! the number of bytes that were sent.
+//!
+//! [`SharedStim`] is a stimulus port handle that can be used from any interrupt handler, and
+//! writes each message without interleaving it with others. The [`buffered`] module queues
+//! messages in memory instead of waiting for the stimulus port.
 
 use core::{fmt, ptr, slice};
 
+pub mod buffered;
+#[cfg(feature = "log")]
+pub mod logger;
+
+use crate::interrupt;
 use crate::peripheral::itm::Stim;
+use crate::peripheral::{DWT, ITM};
+
+/// Bound on the time spent waiting for the stimulus port FIFO
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Timeout {
+    /// Gives up after polling a full FIFO this many times in total
+    ///
+    /// `Spins(0)` never waits: it only writes while the FIFO has room.
+    Spins(u32),
+    /// Gives up once the DWT cycle counter reaches this value
+    ///
+    /// The cycle counter must be enabled, see `DWT::enable_cycle_counter`. The deadline must be
+    /// less than 2<sup>31</sup> cycles away.
+    Deadline(u32),
+}
+
+/// Strategy used while the stimulus port FIFO is full
+trait Wait {
+    /// Returns `true` once the FIFO is ready, or `false` to give up
+    fn ready(&mut self, port: &Stim) -> bool;
+}
+
+/// Waits for as long as needed
+struct Forever;
+
+impl Wait for Forever {
+    #[inline]
+    fn ready(&mut self, port: &Stim) -> bool {
+        while !port.is_fifo_ready() {}
+        true
+    }
+}
+
+/// Waits until a `Timeout` expires
+struct Bounded {
+    timeout: Timeout,
+    spins: u32,
+    expired: bool,
+}
+
+impl Bounded {
+    #[inline]
+    fn new(timeout: Timeout) -> Self {
+        Bounded {
+            timeout,
+            spins: 0,
+            expired: false,
+        }
+    }
+}
+
+impl Wait for Bounded {
+    #[inline]
+    fn ready(&mut self, port: &Stim) -> bool {
+        while !self.expired {
+            if port.is_fifo_ready() {
+                return true;
+            }
+
+            self.expired = match self.timeout {
+                // compared before counting, so `Spins(u32::MAX)` does not overflow
+                Timeout::Spins(max) if self.spins == max => true,
+                Timeout::Spins(_) => {
+                    self.spins += 1;
+                    false
+                }
+                Timeout::Deadline(deadline) => {
+                    (DWT::cycle_count().wrapping_sub(deadline) as i32) >= 0
+                }
+            };
+        }
+        false
+    }
+}
 
 // NOTE assumes that `bytes` is 32-bit aligned
-unsafe fn write_words(stim: &mut Stim, bytes: &[u32]) {
+unsafe fn write_words(stim: &mut Stim, bytes: &[u32], wait: &mut impl Wait) -> usize {
     unsafe {
         let mut p = bytes.as_ptr();
-        for _ in 0..bytes.len() {
-            while !stim.is_fifo_ready() {}
+        for i in 0..bytes.len() {
+            if !wait.ready(stim) {
+                return 4 * i;
+            }
             stim.write_u32(ptr::read(p));
             p = p.offset(1);
         }
+        4 * bytes.len()
     }
 }
 
-/// Writes an aligned byte slice to the ITM.
+/// Writes an aligned byte slice to the ITM, returning the number of bytes sent.
 ///
 /// `buffer` must be 4-byte aligned.
-unsafe fn write_aligned_impl(port: &mut Stim, buffer: &[u8]) {
+unsafe fn write_aligned_impl(port: &mut Stim, buffer: &[u8], wait: &mut impl Wait) -> usize {
     unsafe {
         let len = buffer.len();
 
         if len == 0 {
-            return;
+            return 0;
         }
 
         let split = len & !0b11;
         #[allow(clippy::cast_ptr_alignment)]
-        write_words(
+        let sent = write_words(
             port,
             slice::from_raw_parts(buffer.as_ptr() as *const u32, split >> 2),
+            wait,
         );
+        if sent != split {
+            return sent;
+        }
 
         // 3 bytes or less left
         let mut left = len & 0b11;
@@ -42,7 +137,9 @@ unsafe fn write_aligned_impl(port: &mut Stim, buffer: &[u8]) {
 
         // at least 2 bytes left
         if left > 1 {
-            while !port.is_fifo_ready() {}
+            if !wait.ready(port) {
+                return split;
+            }
 
             #[allow(clippy::cast_ptr_alignment)]
             port.write_u16(ptr::read(ptr as *const u16));
@@ -53,43 +150,31 @@ unsafe fn write_aligned_impl(port: &mut Stim, buffer: &[u8]) {
 
         // final byte
         if left == 1 {
-            while !port.is_fifo_ready() {}
+            if !wait.ready(port) {
+                return len - 1;
+            }
             port.write_u8(*ptr);
         }
-    }
-}
 
-struct Port<'p>(&'p mut Stim);
-
-impl fmt::Write for Port<'_> {
-    #[inline]
-    fn write_str(&mut self, s: &str) -> fmt::Result {
-        write_all(self.0, s.as_bytes());
-        Ok(())
+        len
     }
 }
 
-/// A wrapper type that aligns its contents on a 4-Byte boundary.
-///
-/// ITM transfers are most efficient when the data is 4-Byte-aligned. This type provides an easy
-/// way to accomplish and enforce such an alignment.
-#[repr(align(4))]
-pub struct Aligned<T: ?Sized>(pub T);
-
-/// Writes `buffer` to an ITM port.
-#[allow(clippy::missing_inline_in_public_items)]
-pub fn write_all(port: &mut Stim, buffer: &[u8]) {
+/// Writes `buffer` to an ITM port, returning the number of bytes sent.
+fn write_all_impl(port: &mut Stim, buffer: &[u8], wait: &mut impl Wait) -> usize {
     unsafe {
         let mut len = buffer.len();
         let mut ptr = buffer.as_ptr();
 
         if len == 0 {
-            return;
+            return 0;
         }
 
         // 0x01 OR 0x03
         if ptr as usize % 2 == 1 {
-            while !port.is_fifo_ready() {}
+            if !wait.ready(port) {
+                return 0;
+            }
             port.write_u8(*ptr);
 
             // 0x02 OR 0x04
@@ -101,7 +186,9 @@ pub fn write_all(port: &mut Stim, buffer: &[u8]) {
         if ptr as usize % 4 == 2 {
             if len > 1 {
                 // at least 2 bytes
-                while !port.is_fifo_ready() {}
+                if !wait.ready(port) {
+                    return buffer.len() - len;
+                }
 
                 // We checked the alignment above, so this is safe
                 #[allow(clippy::cast_ptr_alignment)]
@@ -113,19 +200,54 @@ pub fn write_all(port: &mut Stim, buffer: &[u8]) {
             } else {
                 if len == 1 {
                     // last byte
-                    while !port.is_fifo_ready() {}
+                    if !wait.ready(port) {
+                        return buffer.len() - 1;
+                    }
                     port.write_u8(*ptr);
                 }
 
-                return;
+                return buffer.len();
             }
         }
 
         // The remaining data is 4-byte aligned, but might not be a multiple of 4 bytes
-        write_aligned_impl(port, slice::from_raw_parts(ptr, len));
+        buffer.len() - len + write_aligned_impl(port, slice::from_raw_parts(ptr, len), wait)
     }
 }
 
+struct Port<'p, W> {
+    port: &'p mut Stim,
+    wait: W,
+    sent: usize,
+}
+
+impl<W: Wait> fmt::Write for Port<'_, W> {
+    #[inline]
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let sent = write_all_impl(self.port, s.as_bytes(), &mut self.wait);
+        self.sent += sent;
+        if sent == s.len() {
+            Ok(())
+        } else {
+            // stop formatting once the timeout expired
+            Err(fmt::Error)
+        }
+    }
+}
+
+/// A wrapper type that aligns its contents on a 4-Byte boundary.
+///
+/// ITM transfers are most efficient when the data is 4-Byte-aligned. This type provides an easy
+/// way to accomplish and enforce such an alignment.
+#[repr(align(4))]
+pub struct Aligned<T: ?Sized>(pub T);
+
+/// Writes `buffer` to an ITM port.
+#[allow(clippy::missing_inline_in_public_items)]
+pub fn write_all(port: &mut Stim, buffer: &[u8]) {
+    write_all_impl(port, buffer, &mut Forever);
+}
+
 /// Writes a 4-byte aligned `buffer` to an ITM port.
 ///
 /// # Examples
@@ -144,7 +266,9 @@ pub fn write_all(port: &mut Stim, buffer: &[u8]) {
 /// ```
 #[allow(clippy::missing_inline_in_public_items)]
 pub fn write_aligned(port: &mut Stim, buffer: &Aligned<[u8]>) {
-    unsafe { write_aligned_impl(port, &buffer.0) }
+    unsafe {
+        write_aligned_impl(port, &buffer.0, &mut Forever);
+    }
 }
 
 /// Writes `fmt::Arguments` to the ITM `port`
@@ -152,7 +276,13 @@ pub fn write_aligned(port: &mut Stim, buffer: &Aligned<[u8]>) {
 pub fn write_fmt(port: &mut Stim, args: fmt::Arguments) {
     use core::fmt::Write;
 
-    Port(port).write_fmt(args).ok();
+    Port {
+        port,
+        wait: Forever,
+        sent: 0,
+    }
+    .write_fmt(args)
+    .ok();
 }
 
 /// Writes a string to the ITM `port`
@@ -160,3 +290,236 @@ pub fn write_fmt(port: &mut Stim, args: fmt::Arguments) {
 pub fn write_str(port: &mut Stim, string: &str) {
     write_all(port, string.as_bytes())
 }
+
+/// Writes `buffer` to an ITM port, unless it is disabled, giving up once `timeout` expires.
+///
+/// Returns the number of bytes sent, which is 0 if the ITM or the stimulus port is disabled.
+///
+/// # Examples
+///
+/// ```no_run
+/// # use cortex_m::{itm::{self, Timeout}, peripheral::ITM};
+/// # let port = unsafe { &mut (*ITM::PTR).stim[0] };
+/// // never blocks, even if no trace probe is connected
+/// let sent = itm::try_write_all(port, b"Hello, world!\n", Timeout::Spins(1_000));
+/// ```
+#[allow(clippy::missing_inline_in_public_items)]
+pub fn try_write_all(port: &mut Stim, buffer: &[u8], timeout: Timeout) -> usize {
+    if !port.is_enabled() {
+        return 0;
+    }
+    write_all_impl(port, buffer, &mut Bounded::new(timeout))
+}
+
+/// Writes a 4-byte aligned `buffer` to an ITM port, unless it is disabled, giving up once
+/// `timeout` expires.
+///
+/// Returns the number of bytes sent. See [`try_write_all`] for more details.
+#[allow(clippy::missing_inline_in_public_items)]
+pub fn try_write_aligned(port: &mut Stim, buffer: &Aligned<[u8]>, timeout: Timeout) -> usize {
+    if !port.is_enabled() {
+        return 0;
+    }
+    unsafe { write_aligned_impl(port, &buffer.0, &mut Bounded::new(timeout)) }
+}
+
+/// Writes `fmt::Arguments` to the ITM `port`, unless it is disabled, giving up once `timeout`
+/// expires.
+///
+/// Returns the number of bytes sent. See [`try_write_all`] for more details.
+#[inline]
+pub fn try_write_fmt(port: &mut Stim, args: fmt::Arguments, timeout: Timeout) -> usize {
+    use core::fmt::Write;
+
+    if !port.is_enabled() {
+        return 0;
+    }
+    let mut port = Port {
+        port,
+        wait: Bounded::new(timeout),
+        sent: 0,
+    };
+    port.write_fmt(args).ok();
+    port.sent
+}
+
+/// Writes a string to the ITM `port`, unless it is disabled, giving up once `timeout` expires.
+///
+/// Returns the number of bytes sent. See [`try_write_all`] for more details.
+#[inline]
+pub fn try_write_str(port: &mut Stim, string: &str, timeout: Timeout) -> usize {
+    try_write_all(port, string.as_bytes(), timeout)
+}
+
+/// An ITM stimulus port that can be shared between thread mode and interrupt handlers
+///
+/// `SharedStim` is `Sync` and `Copy`, so it can be stored in a `static` and used from any
+/// execution context without passing `ITM` around. Each message is written while holding a lock,
+/// so messages written from different priority levels never interleave.
+///
+/// The lock is a critical section, see [`SharedStim::new`], or a BASEPRI priority ceiling, see
+/// [`SharedStim::with_basepri`], which leaves more urgent interrupts unmasked.
+///
+/// # Examples
+///
+/// ``` no_run
+/// use core::fmt::Write;
+/// use cortex_m::itm::SharedStim;
+///
+/// // NOTE(unsafe) port 0 is only written through `STIM`
+/// static STIM: SharedStim = unsafe { SharedStim::new(0) };
+///
+/// // a single message, even if it is preempted by another `STIM` user
+/// STIM.write_fmt(format_args!("x = {}\n", 42));
+///
+/// // a message assembled from several writes
+/// STIM.lock(|port| {
+///     for i in 0..4 {
+///         write!(port, "{} ", i).ok();
+///     }
+///     port.write_str("\n").ok();
+/// });
+/// ```
+#[derive(Clone, Copy, Debug)]
+pub struct SharedStim {
+    port: u8,
+    lock: Lock,
+}
+
+/// How a `SharedStim` serialises messages
+#[derive(Clone, Copy, Debug)]
+enum Lock {
+    /// Disables all interrupts with PRIMASK
+    CriticalSection,
+    /// Raises BASEPRI to this priority
+    #[cfg(any(armv7m, armv8m_main))]
+    Basepri(u8),
+}
+
+/// Writer handed out by [`SharedStim::lock`]
+///
+/// It implements `core::fmt::Write` and writes to the locked stimulus port.
+pub struct LockedStim<'p> {
+    port: Port<'p, Forever>,
+}
+
+impl LockedStim<'_> {
+    /// Writes `buffer` to the stimulus port
+    #[inline]
+    pub fn write_all(&mut self, buffer: &[u8]) {
+        write_all_impl(self.port.port, buffer, &mut Forever);
+    }
+}
+
+impl fmt::Write for LockedStim<'_> {
+    #[inline]
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.port.write_str(s)
+    }
+}
+
+impl SharedStim {
+    /// Returns a handle to stimulus port `port` that writes messages in a critical section
+    ///
+    /// # Safety
+    ///
+    /// While any `SharedStim` for `port` is in use, the stimulus port must not be written to
+    /// through `ITM` or `ITM::PTR`.
+    #[inline]
+    pub const unsafe fn new(port: u8) -> Self {
+        SharedStim {
+            port,
+            lock: Lock::CriticalSection,
+        }
+    }
+
+    /// Returns a handle to stimulus port `port` that writes messages with BASEPRI raised to
+    /// `ceiling`
+    ///
+    /// Interrupts more urgent than `ceiling` are not masked while a message is written, and must
+    /// not use this handle. `ceiling` is a hardware priority value, like the ones passed to
+    /// `NVIC::set_priority`. With a ceiling of 0, messages are written in a critical section.
+    ///
+    /// **NOTE** This function is only available on ARMv7-M and ARMv8-M Mainline.
+    ///
+    /// # Safety
+    ///
+    /// In addition to the requirements of [`SharedStim::new`], all code writing through a
+    /// `SharedStim` for `port` must run at a priority equal to or lower than `ceiling`.
+    /// Writes panic if a non-zero `ceiling` is not representable with the priority bits
+    /// implemented by the device.
+    #[cfg(any(armv7m, armv8m_main))]
+    #[inline]
+    pub const unsafe fn with_basepri(port: u8, ceiling: u8) -> Self {
+        SharedStim {
+            port,
+            lock: Lock::Basepri(ceiling),
+        }
+    }
+
+    /// Returns the number of the stimulus port
+    #[inline]
+    pub const fn port_number(&self) -> u8 {
+        self.port
+    }
+
+    /// Holds the lock while `f` writes to the stimulus port, e.g. to assemble a message from
+    /// several writes
+    #[inline]
+    pub fn lock<R>(&self, f: impl FnOnce(&mut LockedStim) -> R) -> R {
+        let run = || {
+            // NOTE(unsafe) the port is only written through `SharedStim` handles, which hold the
+            // lock while doing so
+            let port = unsafe { &mut (*ITM::PTR).stim[usize::from(self.port)] };
+            f(&mut LockedStim {
+                port: Port {
+                    port,
+                    wait: Forever,
+                    sent: 0,
+                },
+            })
+        };
+
+        match self.lock {
+            #[cfg(any(armv7m, armv8m_main))]
+            Lock::Basepri(ceiling) if ceiling != 0 => interrupt::with_basepri(ceiling, run),
+            _ => interrupt::free(|_| run()),
+        }
+    }
+
+    /// Writes `buffer` to the stimulus port as a single message
+    #[inline]
+    pub fn write_all(&self, buffer: &[u8]) {
+        self.lock(|port| port.write_all(buffer));
+    }
+
+    /// Writes a string to the stimulus port as a single message
+    #[inline]
+    pub fn write_str(&self, string: &str) {
+        self.write_all(string.as_bytes());
+    }
+
+    /// Writes `fmt::Arguments` to the stimulus port as a single message
+    ///
+    /// This makes `write!(STIM, ...)` work on a `static STIM: SharedStim`.
+    #[inline]
+    pub fn write_fmt(&self, args: fmt::Arguments) {
+        use core::fmt::Write;
+
+        self.lock(|port| port.write_fmt(args).ok());
+    }
+}
+
+impl fmt::Write for SharedStim {
+    #[inline]
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        SharedStim::write_str(self, s);
+        Ok(())
+    }
+
+    #[inline]
+    fn write_fmt(&mut self, args: fmt::Arguments) -> fmt::Result {
+        SharedStim::write_fmt(self, args);
+        Ok(())
+    }
+}
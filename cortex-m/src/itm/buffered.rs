@@ -0,0 +1,484 @@
+//! Buffered ITM output
+//!
+//! Writing to a stimulus port stalls the caller until the port FIFO has room, which at low SWO
+//! baud rates takes milliseconds. An [`ItmBuffer`] instead copies messages into a static ring
+//! buffer, and returns immediately. The buffer is drained to a stimulus port later, from the
+//! PendSV handler or from the idle loop, whenever the FIFO is ready.
+//!
+//! Writes are lock-free: interrupt handlers can write while a lower priority context is in the
+//! middle of a message, and messages are never interleaved. When the buffer is full, whole
+//! messages are dropped and counted.
+//!
+//! The buffer relies on the nesting of exceptions, and must only be used on single core systems.
+//!
+//! # Examples
+//!
+//! ``` no_run
+//! use cortex_m::itm::buffered::ItmBuffer;
+//! use cortex_m::peripheral::ITM;
+//!
+//! // pends PendSV after each message
+//! static BUFFER: ItmBuffer<1024> = ItmBuffer::with_pendsv();
+//!
+//! fn some_interrupt_handler() {
+//!     // returns without waiting for the stimulus port
+//!     BUFFER.write_fmt(format_args!("ticks = {}\n", 42));
+//! }
+//!
+//! fn pend_sv_handler() {
+//!     // NOTE(unsafe) port 0 is only written to here and in the panic handler
+//!     let port = unsafe { &mut (*ITM::PTR).stim[0] };
+//!     BUFFER.drain(port);
+//! }
+//!
+//! fn panic_handler() {
+//!     let port = unsafe { &mut (*ITM::PTR).stim[0] };
+//!     BUFFER.flush(port);
+//! }
+//! ```
+
+use core::cell::UnsafeCell;
+use core::fmt;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::peripheral::SCB;
+use crate::peripheral::itm::Stim;
+
+/// A lock-free ring buffer of ITM messages
+///
+/// `N` is the capacity in bytes, and must be a power of two.
+pub struct ItmBuffer<const N: usize> {
+    buffer: UnsafeCell<[u8; N]>,
+    /// Bytes before this index are reserved by writers
+    reserved: AtomicUsize,
+    /// Bytes before this index are written and can be sent
+    committed: AtomicUsize,
+    /// Bytes before this index were sent
+    read: AtomicUsize,
+    /// Number of writers in the middle of a message
+    writers: AtomicUsize,
+    dropped: AtomicUsize,
+    pendsv: bool,
+}
+
+// NOTE(unsafe) writers only access the bytes they reserved, and the reader only accesses
+// committed bytes
+unsafe impl<const N: usize> Sync for ItmBuffer<N> {}
+
+impl<const N: usize> ItmBuffer<N> {
+    /// Creates an empty buffer, drained by calling [`drain`](Self::drain) periodically
+    #[inline]
+    pub const fn new() -> Self {
+        const { assert!(N.is_power_of_two(), "the capacity must be a power of two") };
+
+        ItmBuffer {
+            buffer: UnsafeCell::new([0; N]),
+            reserved: AtomicUsize::new(0),
+            committed: AtomicUsize::new(0),
+            read: AtomicUsize::new(0),
+            writers: AtomicUsize::new(0),
+            dropped: AtomicUsize::new(0),
+            pendsv: false,
+        }
+    }
+
+    /// Creates an empty buffer that pends the PendSV exception after each message
+    ///
+    /// The PendSV handler must then call [`drain`](Self::drain).
+    #[inline]
+    pub const fn with_pendsv() -> Self {
+        let mut buffer = Self::new();
+        buffer.pendsv = true;
+        buffer
+    }
+
+    /// Returns the capacity of the buffer in bytes
+    #[inline]
+    pub const fn capacity(&self) -> usize {
+        N
+    }
+
+    /// Returns the number of bytes waiting to be sent
+    #[inline]
+    pub fn len(&self) -> usize {
+        let read = self.read.load(Ordering::Relaxed);
+        self.committed.load(Ordering::Relaxed).wrapping_sub(read)
+    }
+
+    /// Returns `true` if there are no bytes waiting to be sent
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the number of messages dropped because the buffer was full
+    #[inline]
+    pub fn dropped(&self) -> usize {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    /// Returns the number of messages dropped because the buffer was full, and resets it to 0
+    #[inline]
+    pub fn take_dropped(&self) -> usize {
+        self.dropped.swap(0, Ordering::Relaxed)
+    }
+
+    /// Copies `message` into the buffer
+    ///
+    /// Returns `false`, and drops the whole message, if the buffer does not have room for it.
+    #[inline]
+    pub fn write_all(&self, message: &[u8]) -> bool {
+        self.write_with(message.len(), |slot| slot.write(message))
+    }
+
+    /// Copies `message` into the buffer
+    ///
+    /// See [`write_all`](Self::write_all).
+    #[inline]
+    pub fn write_str(&self, message: &str) -> bool {
+        self.write_all(message.as_bytes())
+    }
+
+    /// Formats `args` into the buffer
+    ///
+    /// `args` is formatted twice: once to compute the size of the message, and once to copy it
+    /// into the buffer. If the second pass produces less output, the message is padded with
+    /// spaces; if it produces more, the message is truncated.
+    ///
+    /// Returns `false`, and drops the whole message, if the buffer does not have room for it.
+    #[inline]
+    pub fn write_fmt(&self, args: fmt::Arguments) -> bool {
+        use core::fmt::Write;
+
+        let mut counter = Counter(0);
+        if counter.write_fmt(args).is_err() {
+            return false;
+        }
+
+        self.write_with(counter.0, |slot| {
+            slot.write_fmt(args).ok();
+            while slot.len != 0 {
+                slot.write(b" ");
+            }
+        })
+    }
+
+    /// Sends buffered bytes to `port` while its FIFO is ready, without waiting
+    ///
+    /// Returns the number of bytes sent. Bytes left in the buffer are sent by the next call.
+    ///
+    /// The buffer must only be drained from one context at a time, e.g. only from the PendSV
+    /// handler.
+    #[inline]
+    pub fn drain(&self, port: &mut Stim) -> usize {
+        let end = self.committed.load(Ordering::Acquire);
+        self.send(port, end, false)
+    }
+
+    /// Sends all buffered bytes to `port`, waiting for its FIFO as needed
+    ///
+    /// This is meant to be called from the panic handler: it also sends messages that were being
+    /// written when the panic occurred, which may be incomplete. If the ITM or `port` is
+    /// disabled, the buffered bytes are discarded instead.
+    ///
+    /// Returns the number of bytes sent.
+    #[inline]
+    pub fn flush(&self, port: &mut Stim) -> usize {
+        let end = self.reserved.load(Ordering::Acquire);
+        self.committed.store(end, Ordering::Release);
+        if !port.is_enabled() {
+            self.read.store(end, Ordering::Release);
+            return 0;
+        }
+        self.send(port, end, true)
+    }
+
+    fn send(&self, port: &mut impl Port, end: usize, block: bool) -> usize {
+        let start = self.read.load(Ordering::Relaxed);
+        let mut read = start;
+
+        while read != end {
+            if !port.is_ready() {
+                if block {
+                    continue;
+                }
+                break;
+            }
+
+            // send a full word when possible, as a single stimulus port packet
+            if end.wrapping_sub(read) >= 4 {
+                let word = [
+                    self.byte(read),
+                    self.byte(read.wrapping_add(1)),
+                    self.byte(read.wrapping_add(2)),
+                    self.byte(read.wrapping_add(3)),
+                ];
+                port.write_u32(u32::from_le_bytes(word));
+                read = read.wrapping_add(4);
+            } else {
+                port.write_u8(self.byte(read));
+                read = read.wrapping_add(1);
+            }
+
+            // free the space for writers
+            self.read.store(read, Ordering::Release);
+        }
+
+        read.wrapping_sub(start)
+    }
+
+    fn byte(&self, index: usize) -> u8 {
+        // NOTE(unsafe) the byte was committed, and no writer accesses it until it is read
+        unsafe { *(self.buffer.get() as *const u8).add(index % N) }
+    }
+
+    /// Reserves `len` bytes, fills them with `f`, and commits them
+    fn write_with(&self, len: usize, f: impl FnOnce(&mut Slot<N>)) -> bool {
+        self.writers.fetch_add(1, Ordering::Acquire);
+
+        let start = loop {
+            let reserved = self.reserved.load(Ordering::Relaxed);
+            let read = self.read.load(Ordering::Acquire);
+            if N - reserved.wrapping_sub(read) < len {
+                self.dropped.fetch_add(1, Ordering::Relaxed);
+                self.release();
+                return false;
+            }
+            if self
+                .reserved
+                .compare_exchange_weak(
+                    reserved,
+                    reserved.wrapping_add(len),
+                    Ordering::Relaxed,
+                    Ordering::Relaxed,
+                )
+                .is_ok()
+            {
+                break reserved;
+            }
+        };
+
+        f(&mut Slot {
+            buffer: self.buffer.get() as *mut u8,
+            index: start,
+            len,
+        });
+
+        self.release();
+        if self.pendsv {
+            SCB::set_pendsv();
+        }
+        true
+    }
+
+    /// Ends a write, and commits all reserved bytes if no other write is in progress
+    fn release(&self) {
+        if self.writers.fetch_sub(1, Ordering::AcqRel) != 1 {
+            // the write we preempted commits our bytes together with its own
+            return;
+        }
+
+        // Writes that preempt us from here on run to completion before we resume, so every
+        // reserved byte is written. A preempting write may already have committed more bytes
+        // than we observe here: never move `committed` backwards.
+        let reserved = self.reserved.load(Ordering::Acquire);
+        let mut committed = self.committed.load(Ordering::Relaxed);
+        while (reserved.wrapping_sub(committed) as isize) > 0 {
+            match self.committed.compare_exchange_weak(
+                committed,
+                reserved,
+                Ordering::Release,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => break,
+                Err(current) => committed = current,
+            }
+        }
+    }
+}
+
+impl<const N: usize> Default for ItmBuffer<N> {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Destination of the buffered bytes
+trait Port {
+    fn is_ready(&self) -> bool;
+    fn write_u8(&mut self, value: u8);
+    fn write_u32(&mut self, value: u32);
+}
+
+impl Port for Stim {
+    fn is_ready(&self) -> bool {
+        self.is_fifo_ready()
+    }
+
+    fn write_u8(&mut self, value: u8) {
+        Stim::write_u8(self, value)
+    }
+
+    fn write_u32(&mut self, value: u32) {
+        Stim::write_u32(self, value)
+    }
+}
+
+/// Space reserved for a message in the ring buffer
+struct Slot<const N: usize> {
+    buffer: *mut u8,
+    index: usize,
+    /// Bytes left in the reservation
+    len: usize,
+}
+
+impl<const N: usize> Slot<N> {
+    /// Copies as much of `bytes` as fits
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes.iter().take(self.len) {
+            // NOTE(unsafe) the byte is reserved for this slot
+            unsafe { *self.buffer.add(self.index % N) = byte };
+            self.index = self.index.wrapping_add(1);
+        }
+        self.len -= bytes.len().min(self.len);
+    }
+}
+
+impl<const N: usize> fmt::Write for Slot<N> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.write(s.as_bytes());
+        Ok(())
+    }
+}
+
+/// Computes the length of formatted output
+struct Counter(usize);
+
+impl fmt::Write for Counter {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.0 += s.len();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Records the bytes sent, accepting `ready` more writes
+    struct TestPort {
+        bytes: [u8; 32],
+        len: usize,
+        ready: usize,
+    }
+
+    impl TestPort {
+        fn new(ready: usize) -> Self {
+            TestPort {
+                bytes: [0; 32],
+                len: 0,
+                ready,
+            }
+        }
+
+        fn sent(&self) -> &[u8] {
+            &self.bytes[..self.len]
+        }
+    }
+
+    impl Port for TestPort {
+        fn is_ready(&self) -> bool {
+            self.ready != 0
+        }
+
+        fn write_u8(&mut self, value: u8) {
+            self.ready -= 1;
+            self.bytes[self.len] = value;
+            self.len += 1;
+        }
+
+        fn write_u32(&mut self, value: u32) {
+            self.ready -= 1;
+            self.bytes[self.len..self.len + 4].copy_from_slice(&value.to_le_bytes());
+            self.len += 4;
+        }
+    }
+
+    fn drain<const N: usize>(buffer: &ItmBuffer<N>, port: &mut TestPort) -> usize {
+        buffer.send(port, buffer.committed.load(Ordering::Acquire), false)
+    }
+
+    #[test]
+    fn wraps_around() {
+        let buffer = ItmBuffer::<8>::new();
+        let mut port = TestPort::new(usize::MAX);
+
+        assert!(buffer.write_all(b"abcdef"));
+        assert_eq!(drain(&buffer, &mut port), 6);
+
+        // stored at indices 6, 7, 0, 1 and 2
+        assert!(buffer.write_all(b"ghijk"));
+        assert_eq!(buffer.len(), 5);
+        assert_eq!(drain(&buffer, &mut port), 5);
+        assert_eq!(port.sent(), b"abcdefghijk");
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn full_buffer_drains_partially() {
+        let buffer = ItmBuffer::<8>::new();
+        assert!(buffer.write_all(b"abcdefgh"));
+        assert!(!buffer.write_all(b"i"));
+
+        // the FIFO accepts a single word
+        let mut port = TestPort::new(1);
+        assert_eq!(drain(&buffer, &mut port), 4);
+        assert_eq!(port.sent(), b"abcd");
+        assert_eq!(buffer.len(), 4);
+
+        // the sent bytes are free again
+        assert!(buffer.write_all(b"ijkl"));
+        port.ready = usize::MAX;
+        assert_eq!(drain(&buffer, &mut port), 8);
+        assert_eq!(port.sent(), b"abcdefghijkl");
+    }
+
+    #[test]
+    fn nested_write_is_committed_with_the_preempted_one() {
+        let buffer = ItmBuffer::<16>::new();
+
+        assert!(buffer.write_with(3, |slot| {
+            // an interrupt handler preempts the write after its reservation
+            assert!(buffer.write_all(b"xy"));
+            // its bytes wait for the preempted write, whose bytes come first
+            assert_eq!(buffer.len(), 0);
+            slot.write(b"abc");
+        }));
+        assert_eq!(buffer.len(), 5);
+
+        let mut port = TestPort::new(usize::MAX);
+        assert_eq!(drain(&buffer, &mut port), 5);
+        assert_eq!(port.sent(), b"abcxy");
+    }
+
+    #[test]
+    fn drains_after_overflow() {
+        let buffer = ItmBuffer::<8>::new();
+        assert!(buffer.write_all(b"abcde"));
+        assert!(!buffer.write_all(b"fghi"));
+        assert!(!buffer.write_all(b"jklm"));
+        assert_eq!(buffer.dropped(), 2);
+
+        // dropped messages leave nothing behind
+        let mut port = TestPort::new(usize::MAX);
+        assert_eq!(drain(&buffer, &mut port), 5);
+        assert_eq!(port.sent(), b"abcde");
+
+        assert!(buffer.write_all(b"nopq"));
+        assert_eq!(drain(&buffer, &mut port), 4);
+        assert_eq!(port.sent(), b"abcdenopq");
+        assert_eq!(buffer.take_dropped(), 2);
+        assert_eq!(buffer.dropped(), 0);
+    }
+}
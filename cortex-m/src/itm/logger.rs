@@ -0,0 +1,205 @@
+//! `log` backend over ITM stimulus ports
+//!
+//! **NOTE** This module requires the `log` feature.
+//!
+//! Each log level is emitted on its own stimulus port, so a trace viewer can separate or filter
+//! levels. Records are formatted as `[<cycles>] <LEVEL> <target>: <message>`, where the optional
+//! `[<cycles>]` prefix is the value of the DWT cycle counter.
+//!
+//! Records are written inside a critical section, so records logged from interrupt handlers are
+//! not interleaved with the one they interrupted. Records logged at a disabled level, or on a
+//! disabled stimulus port, cost no more than a register read.
+//!
+//! # Examples
+//!
+//! ``` no_run
+//! use cortex_m::itm::logger::{self, ItmLogger, LevelPorts};
+//! use cortex_m::itm::Timeout;
+//! use cortex_m::peripheral::itm::ItmSettings;
+//! use cortex_m::peripheral::Peripherals;
+//! use log::LevelFilter;
+//!
+//! static LOGGER: ItmLogger = ItmLogger::new(LevelFilter::Info)
+//!     .with_ports(LevelPorts {
+//!         error: 0,
+//!         warn: 0,
+//!         info: 1,
+//!         debug: 2,
+//!         trace: 2,
+//!     })
+//!     .with_timestamps(true)
+//!     .with_timeout(Some(Timeout::Spins(10_000)));
+//!
+//! let mut p = Peripherals::take().unwrap();
+//! p.DCB.enable_trace();
+//! p.DWT.enable_cycle_counter();
+//! p.ITM
+//!     .configure(ItmSettings {
+//!         enabled_ports: 0b111,
+//!         ..ItmSettings::default()
+//!     })
+//!     .unwrap();
+//!
+//! logger::init(&LOGGER).unwrap();
+//! log::info!("ready");
+//! ```
+
+use core::fmt::{self, Write};
+
+use log::{Level, LevelFilter, Log, Metadata, Record, SetLoggerError};
+
+use crate::interrupt;
+use crate::itm::{Bounded, Forever, Port, Timeout, Wait};
+use crate::peripheral::{DWT, ITM};
+
+/// Stimulus port used for each log level
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct LevelPorts {
+    /// Port of `Level::Error` records
+    pub error: u8,
+    /// Port of `Level::Warn` records
+    pub warn: u8,
+    /// Port of `Level::Info` records
+    pub info: u8,
+    /// Port of `Level::Debug` records
+    pub debug: u8,
+    /// Port of `Level::Trace` records
+    pub trace: u8,
+}
+
+impl LevelPorts {
+    /// Uses `port` for all levels
+    #[inline]
+    pub const fn all(port: u8) -> Self {
+        LevelPorts {
+            error: port,
+            warn: port,
+            info: port,
+            debug: port,
+            trace: port,
+        }
+    }
+
+    /// Returns the port of `level`
+    #[inline]
+    pub const fn port(&self, level: Level) -> u8 {
+        match level {
+            Level::Error => self.error,
+            Level::Warn => self.warn,
+            Level::Info => self.info,
+            Level::Debug => self.debug,
+            Level::Trace => self.trace,
+        }
+    }
+}
+
+/// A `log::Log` implementation writing to ITM stimulus ports
+#[derive(Clone, Copy, Debug)]
+pub struct ItmLogger {
+    level: LevelFilter,
+    ports: LevelPorts,
+    timestamps: bool,
+    timeout: Option<Timeout>,
+}
+
+impl ItmLogger {
+    /// Creates a logger emitting records up to `level` on stimulus port 0, without timestamps
+    #[inline]
+    pub const fn new(level: LevelFilter) -> Self {
+        ItmLogger {
+            level,
+            ports: LevelPorts::all(0),
+            timestamps: false,
+            timeout: None,
+        }
+    }
+
+    /// Selects the stimulus port of each log level
+    #[inline]
+    pub const fn with_ports(mut self, ports: LevelPorts) -> Self {
+        self.ports = ports;
+        self
+    }
+
+    /// Prefixes records with the value of the DWT cycle counter
+    ///
+    /// The cycle counter must be enabled, see `DWT::enable_cycle_counter`.
+    #[inline]
+    pub const fn with_timestamps(mut self, timestamps: bool) -> Self {
+        self.timestamps = timestamps;
+        self
+    }
+
+    /// Bounds the time spent writing each record
+    ///
+    /// With `None`, the default, writes wait for the stimulus port FIFO for as long as needed,
+    /// like `iprintln!`. With a timeout, the rest of a record is dropped once it expires, see
+    /// [`try_write_all`](crate::itm::try_write_all).
+    #[inline]
+    pub const fn with_timeout(mut self, timeout: Option<Timeout>) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Returns the most verbose level emitted by the logger
+    #[inline]
+    pub const fn level(&self) -> LevelFilter {
+        self.level
+    }
+
+    fn write(&self, port: u8, record: &Record, wait: impl Wait) -> fmt::Result {
+        // NOTE(unsafe) the stimulus port is only written inside a critical section
+        let port = unsafe { &mut (*ITM::PTR).stim[usize::from(port)] };
+        let mut port = Port {
+            port,
+            wait,
+            sent: 0,
+        };
+        if self.timestamps {
+            write!(port, "[{:10}] ", DWT::cycle_count())?;
+        }
+        writeln!(
+            port,
+            "{:<5} {}: {}",
+            record.level(),
+            record.target(),
+            record.args()
+        )
+    }
+}
+
+impl Log for ItmLogger {
+    #[inline]
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.level
+            && ITM::is_enabled()
+            && ITM::is_port_enabled(self.ports.port(metadata.level()))
+    }
+
+    #[inline]
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        let port = self.ports.port(record.level());
+        interrupt::free(|_| {
+            match self.timeout {
+                None => self.write(port, record, Forever),
+                Some(timeout) => self.write(port, record, Bounded::new(timeout)),
+            }
+            .ok();
+        });
+    }
+
+    #[inline]
+    fn flush(&self) {}
+}
+
+/// Installs `logger` as the global logger, and sets the maximum log level to its level
+#[inline]
+pub fn init(logger: &'static ItmLogger) -> Result<(), SetLoggerError> {
+    log::set_logger(logger)?;
+    log::set_max_level(logger.level);
+    Ok(())
+}
@@ -0,0 +1,264 @@
+//! Interrupt latency measurement
+//!
+//! A [`LatencyProbe`] measures the number of cycles between pending an interrupt and entering its
+//! handler: [`LatencyProbe::trigger`] records the DWT cycle counter and pends the interrupt with
+//! `NVIC::pend`, and the handler calls [`LatencyProbe::on_entry`] first thing to record the cycle
+//! counter again. The measurements are accumulated into [`LatencyStats`]: minimum, maximum, mean
+//! and a histogram.
+//!
+//! [`LatencyProbe::trigger_masked`] pends the interrupt from inside a critical section instead,
+//! so the measurement includes the time interrupts stay masked: this measures the effect of
+//! (nested) critical sections on the latency.
+//!
+//! The cycle counter must be enabled, see `DWT::enable_cycle_counter`. The measured interrupt
+//! must be enabled, and must not be used for anything else while measuring.
+//!
+//! **NOTE** This module is not available on ARMv6-M, which has no cycle counter.
+//!
+//! # Examples
+//!
+//! Asserting a latency budget, e.g. in a test running on hardware:
+//!
+//! ``` ignore
+//! use cortex_m::latency::LatencyProbe;
+//!
+//! // histogram of 16 buckets of 4 cycles
+//! static PROBE: LatencyProbe<16> = LatencyProbe::new(4);
+//!
+//! #[entry]
+//! fn main() -> ! {
+//!     // .. enable the cycle counter and the `TIMER0` interrupt
+//!     for _ in 0..1000 {
+//!         PROBE.trigger(Interrupt::TIMER0);
+//!     }
+//!     // with interrupts masked for the duration of `work`
+//!     PROBE.trigger_masked(Interrupt::TIMER0, || work());
+//!
+//!     let stats = PROBE.stats();
+//!     assert!(stats.max().unwrap() <= 30);
+//!     loop {}
+//! }
+//!
+//! #[interrupt]
+//! fn TIMER0() {
+//!     PROBE.on_entry();
+//! }
+//! ```
+
+use core::cell::RefCell;
+use core::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+
+use crate::interrupt::{self, InterruptNumber, Mutex};
+use crate::peripheral::{DWT, NVIC};
+
+/// Statistics of latency measurements, in cycles
+///
+/// The histogram has `BUCKETS` buckets of `bucket_width` cycles each: bucket `n` counts the
+/// measurements in `n * bucket_width..(n + 1) * bucket_width`. Longer measurements are counted
+/// as overflows.
+///
+/// # Examples
+///
+/// ```
+/// use cortex_m::latency::LatencyStats;
+///
+/// let mut stats = LatencyStats::<4>::new(10);
+/// for cycles in [12, 15, 31, 60] {
+///     stats.record(cycles);
+/// }
+///
+/// assert_eq!(stats.count(), 4);
+/// assert_eq!(stats.min(), Some(12));
+/// assert_eq!(stats.max(), Some(60));
+/// assert_eq!(stats.mean(), Some(29));
+/// assert_eq!(stats.buckets(), &[0, 2, 0, 1]);
+/// assert_eq!(stats.overflows(), 1);
+/// ```
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct LatencyStats<const BUCKETS: usize> {
+    count: u32,
+    min: u32,
+    max: u32,
+    total: u64,
+    bucket_width: u32,
+    buckets: [u32; BUCKETS],
+    overflows: u32,
+}
+
+impl<const BUCKETS: usize> LatencyStats<BUCKETS> {
+    /// Creates empty statistics, with histogram buckets of `bucket_width` cycles
+    ///
+    /// # Panics
+    ///
+    /// Panics if `bucket_width` is 0.
+    #[inline]
+    pub const fn new(bucket_width: u32) -> Self {
+        assert!(
+            bucket_width != 0,
+            "the buckets must be at least 1 cycle wide"
+        );
+
+        LatencyStats {
+            count: 0,
+            min: u32::MAX,
+            max: 0,
+            total: 0,
+            bucket_width,
+            buckets: [0; BUCKETS],
+            overflows: 0,
+        }
+    }
+
+    /// Adds a measurement of `cycles`
+    #[inline]
+    pub fn record(&mut self, cycles: u32) {
+        self.count = self.count.saturating_add(1);
+        self.min = self.min.min(cycles);
+        self.max = self.max.max(cycles);
+        self.total += u64::from(cycles);
+        match self.buckets.get_mut((cycles / self.bucket_width) as usize) {
+            Some(bucket) => *bucket = bucket.saturating_add(1),
+            None => self.overflows = self.overflows.saturating_add(1),
+        }
+    }
+
+    /// Returns the number of measurements
+    #[inline]
+    pub const fn count(&self) -> u32 {
+        self.count
+    }
+
+    /// Returns the shortest measurement, or `None` if there are none
+    #[inline]
+    pub const fn min(&self) -> Option<u32> {
+        if self.count == 0 {
+            None
+        } else {
+            Some(self.min)
+        }
+    }
+
+    /// Returns the longest measurement, or `None` if there are none
+    #[inline]
+    pub const fn max(&self) -> Option<u32> {
+        if self.count == 0 {
+            None
+        } else {
+            Some(self.max)
+        }
+    }
+
+    /// Returns the mean of the measurements, rounded down, or `None` if there are none
+    #[inline]
+    pub const fn mean(&self) -> Option<u32> {
+        if self.count == 0 {
+            None
+        } else {
+            Some((self.total / self.count as u64) as u32)
+        }
+    }
+
+    /// Returns the width of the histogram buckets in cycles
+    #[inline]
+    pub const fn bucket_width(&self) -> u32 {
+        self.bucket_width
+    }
+
+    /// Returns the histogram
+    #[inline]
+    pub const fn buckets(&self) -> &[u32; BUCKETS] {
+        &self.buckets
+    }
+
+    /// Returns the number of measurements longer than the histogram
+    #[inline]
+    pub const fn overflows(&self) -> u32 {
+        self.overflows
+    }
+}
+
+/// Measures the latency of an interrupt, see the [module documentation](self)
+pub struct LatencyProbe<const BUCKETS: usize> {
+    /// Cycle count when the interrupt was pended
+    start: AtomicU32,
+    /// Set while waiting for the handler
+    armed: AtomicBool,
+    stats: Mutex<RefCell<LatencyStats<BUCKETS>>>,
+}
+
+impl<const BUCKETS: usize> LatencyProbe<BUCKETS> {
+    /// Creates a probe, whose histogram has buckets of `bucket_width` cycles
+    ///
+    /// # Panics
+    ///
+    /// Panics if `bucket_width` is 0.
+    #[inline]
+    pub const fn new(bucket_width: u32) -> Self {
+        LatencyProbe {
+            start: AtomicU32::new(0),
+            armed: AtomicBool::new(false),
+            stats: Mutex::new(RefCell::new(LatencyStats::new(bucket_width))),
+        }
+    }
+
+    /// Pends `interrupt`, and records the cycle count
+    ///
+    /// When called from a context of lower priority than `interrupt`, the handler runs, and the
+    /// measurement is recorded, before this returns.
+    #[inline]
+    pub fn trigger<I>(&self, interrupt: I)
+    where
+        I: InterruptNumber,
+    {
+        self.armed.store(true, Ordering::Relaxed);
+        self.start.store(DWT::cycle_count(), Ordering::Release);
+        NVIC::pend(interrupt);
+    }
+
+    /// Pends `interrupt` from inside a critical section, then runs `f` before leaving it
+    ///
+    /// The measurement includes the duration of `f`, and of the critical sections nested in it,
+    /// plus the time it takes to leave the critical section.
+    #[inline]
+    pub fn trigger_masked<I, F>(&self, interrupt: I, f: F)
+    where
+        I: InterruptNumber,
+        F: FnOnce(),
+    {
+        interrupt::free(|_| {
+            self.trigger(interrupt);
+            f();
+        });
+    }
+
+    /// Records the latency of the interrupt pended by [`trigger`](Self::trigger)
+    ///
+    /// This must be called first thing in the handler of the measured interrupt. Returns the
+    /// latency in cycles, or `None` if the interrupt was not pended by this probe.
+    #[inline]
+    pub fn on_entry(&self) -> Option<u32> {
+        let now = DWT::cycle_count();
+        if !self.armed.swap(false, Ordering::Relaxed) {
+            return None;
+        }
+
+        let cycles = now.wrapping_sub(self.start.load(Ordering::Acquire));
+        interrupt::free(|cs| self.stats.borrow(cs).borrow_mut().record(cycles));
+        Some(cycles)
+    }
+
+    /// Returns the statistics of the measurements so far
+    #[inline]
+    pub fn stats(&self) -> LatencyStats<BUCKETS> {
+        interrupt::free(|cs| *self.stats.borrow(cs).borrow())
+    }
+
+    /// Discards the measurements
+    #[inline]
+    pub fn reset(&self) {
+        interrupt::free(|cs| {
+            let mut stats = self.stats.borrow(cs).borrow_mut();
+            *stats = LatencyStats::new(stats.bucket_width);
+        });
+    }
+}
@@ -24,6 +24,43 @@
 //! configuration. For minimal interrupt latency, you can achieve inlining by enabling
 //! [linker-plugin-based LTO](https://doc.rust-lang.org/rustc/linker-plugin-lto.html).
 //!
+//! ## `critical-section-basepri`
+//!
+//! This feature enables a [`critical-section`](https://github.com/rust-embedded/critical-section)
+//! implementation for single-core targets that raises BASEPRI to a threshold instead of
+//! disabling interrupts globally. Interrupts more urgent than the threshold, e.g. for motor
+//! control, are never blocked by critical sections, and must not use `critical_section::with`
+//! or share data with code that does.
+//!
+//! The threshold is a hardware priority value, like the ones passed to `NVIC::set_priority`, set
+//! at build time by the `CORTEX_M_CS_BASEPRI_THRESHOLD` environment variable, in decimal or
+//! `0x` hexadecimal, e.g. in `.cargo/config.toml`:
+//!
+//! ```toml
+//! [env]
+//! CORTEX_M_CS_BASEPRI_THRESHOLD = "0x40"
+//! ```
+//!
+//! The threshold must be representable with the priority bits implemented by the device, which
+//! are the most significant ones: e.g. on a device with 4 priority bits, `0x08` reads back from
+//! BASEPRI as 0, and critical sections would mask nothing. Debug builds check this on entry of
+//! every critical section. [`Priority::to_hw`](crate::priority::Priority::to_hw) computes valid
+//! values.
+//!
+//! BASEPRI is only implemented on ARMv7-M and ARMv8-M Mainline: enabling this feature on other
+//! targets is a compile error. Like `critical-section-single-core`, it is **unsound** on
+//! multi-core targets and for code running in unprivileged mode, and the two features are
+//! mutually exclusive.
+//!
+//! ## `critical-section-timing`
+//!
+//! This feature measures how long [`interrupt::free`] and the `critical-section-single-core`
+//! implementation keep interrupts disabled, using the DWT cycle counter, and records the location
+//! of the longest critical section. See [`critical_section_timing`] for the statistics.
+//!
+//! It adds a few cycles to every critical section, and is meant for debugging. The cycle counter
+//! is not implemented on ARMv6-M: enabling this feature there is a compile error.
+//!
 //! ## `cm7-r0p1`
 //!
 //! This feature enables workarounds for errata found on Cortex-M7 chips with revision r0p1. Some
@@ -49,6 +86,11 @@
 //! [Linker-Plugin LTO]: https://doc.rust-lang.org/stable/rustc/linker-plugin-lto.html
 //! [rust-lang/rust#75940]: https://github.com/rust-lang/rust/issues/75940
 //!
+//! ## `log`
+//!
+//! This feature enables [`itm::logger`], a backend for the [`log`](https://docs.rs/log) crate that
+//! emits log records over ITM stimulus ports. It is only available on ARMv7-M and newer.
+//!
 //! ## `inline-asm`
 //!
 //! This feature is deprecated.
@@ -86,6 +128,24 @@
 #[cfg(all(feature = "cm7-r0p1", not(armv7em)))]
 compile_error!("The feature \"cm7-r0p1\" is only compatible with the armv7em target");
 
+#[cfg(all(feature = "critical-section-basepri", any(armv6m, armv8m_base)))]
+compile_error!(
+    "The feature \"critical-section-basepri\" requires BASEPRI, which is not implemented on ARMv6-M and ARMv8-M Baseline"
+);
+
+#[cfg(all(
+    feature = "critical-section-basepri",
+    feature = "critical-section-single-core"
+))]
+compile_error!(
+    "The features \"critical-section-basepri\" and \"critical-section-single-core\" are mutually exclusive"
+);
+
+#[cfg(all(feature = "critical-section-timing", armv6m))]
+compile_error!(
+    "The feature \"critical-section-timing\" requires the DWT cycle counter, which is not implemented on ARMv6-M"
+);
+
 extern crate bare_metal;
 extern crate volatile_register;
 
@@ -95,20 +155,33 @@ mod macros;
 pub mod asm;
 #[cfg(armv8m)]
 pub mod cmse;
+#[cfg(all(cortex_m, not(armv6m), feature = "critical-section-timing"))]
+pub mod critical_section_timing;
+#[cfg(all(not(armv6m), not(armv8m_base)))]
+pub mod debug_monitor;
+pub mod deferred;
 pub mod delay;
 pub mod interrupt;
 #[cfg(all(not(armv6m), not(armv8m_base)))]
 pub mod itm;
+#[cfg(not(armv6m))]
+pub mod latency;
 pub mod peripheral;
 pub mod prelude;
+pub mod priority;
 pub mod psp;
 pub mod register;
+#[cfg(all(not(armv6m), not(armv8m)))]
+pub mod stack_guard;
 
 pub use crate::peripheral::Peripherals;
 
 #[cfg(all(cortex_m, feature = "critical-section-single-core"))]
 mod critical_section;
 
+#[cfg(all(any(armv7m, armv8m_main), feature = "critical-section-basepri"))]
+mod critical_section_basepri;
+
 /// Used to reexport items for use in macros. Do not use directly.
 /// Not covered by semver guarantees.
 #[doc(hidden)]
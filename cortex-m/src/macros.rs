@@ -127,3 +127,41 @@ const CPASS: () = ();
 /// ```
 #[allow(dead_code)]
 const CPASS_ATTR: () = ();
+
+/// Defines the `PendSV` exception handler, which runs the callbacks of a deferred work queue
+///
+/// Without arguments, the handler runs the callbacks queued with [`deferred::defer`]. Given the
+/// path to a `static` [`DeferredQueue`], it runs the callbacks of that queue instead.
+///
+/// The handler is linked in place of the `PendSV` handler of `cortex-m-rt`: it must not be
+/// defined elsewhere, e.g. with `#[exception]`.
+///
+/// [`deferred::defer`]: crate::deferred::defer
+/// [`DeferredQueue`]: crate::deferred::DeferredQueue
+///
+/// # Example
+///
+/// ``` no_run
+/// use cortex_m::deferred::DeferredQueue;
+///
+/// static QUEUE: DeferredQueue<8> = DeferredQueue::new();
+///
+/// cortex_m::deferred_pendsv_handler!(QUEUE);
+/// ```
+#[macro_export]
+macro_rules! deferred_pendsv_handler {
+    () => {
+        $crate::deferred_pendsv_handler!(@handler $crate::deferred::run_pending());
+    };
+    ($queue:path) => {
+        $crate::deferred_pendsv_handler!(@handler $queue.run_pending());
+    };
+    (@handler $run:expr) => {
+        const _: () = {
+            #[unsafe(export_name = "PendSV")]
+            unsafe extern "C" fn __cortex_m_deferred_pendsv() {
+                $run
+            }
+        };
+    };
+}
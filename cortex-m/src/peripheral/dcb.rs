@@ -6,6 +6,21 @@ use crate::peripheral::DCB;
 use core::ptr;
 
 const DCB_DEMCR_TRCENA: u32 = 1 << 24;
+#[cfg(all(not(armv6m), not(armv8m_base)))]
+mod monitor_consts {
+    pub const DCB_DEMCR_MON_EN: u32 = 1 << 16;
+    pub const DCB_DEMCR_MON_PEND: u32 = 1 << 17;
+    pub const DCB_DEMCR_MON_STEP: u32 = 1 << 18;
+    pub const DCB_DEMCR_MON_REQ: u32 = 1 << 19;
+}
+#[cfg(all(not(armv6m), not(armv8m_base)))]
+use self::monitor_consts::*;
+
+const DCB_DHCSR_S_HALT: u32 = 1 << 17;
+const DCB_DHCSR_S_SLEEP: u32 = 1 << 18;
+const DCB_DHCSR_S_LOCKUP: u32 = 1 << 19;
+const DCB_DHCSR_S_RETIRE_ST: u32 = 1 << 24;
+const DCB_DHCSR_S_RESET_ST: u32 = 1 << 25;
 
 /// Register block
 #[repr(C)]
@@ -20,6 +35,53 @@ pub struct RegisterBlock {
     pub demcr: RW<u32>,
 }
 
+/// Processor status reported by the Debug Halting Control and Status Register
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct DebugStatus {
+    /// The processor is in Debug state (S_HALT)
+    pub halted: bool,
+    /// The processor is sleeping (S_SLEEP)
+    pub sleeping: bool,
+    /// The processor is locked up because of an unrecoverable exception (S_LOCKUP)
+    pub locked_up: bool,
+    /// An instruction completed since the last DHCSR read (S_RETIRE_ST)
+    pub retired: bool,
+    /// The processor was reset since the last DHCSR read (S_RESET_ST)
+    pub reset: bool,
+}
+
+/// Debug events that can halt the processor on exception entry, see
+/// [`DCB::enable_vector_catch`]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[repr(u8)]
+pub enum VectorCatch {
+    /// Reset of the processor (VC_CORERESET)
+    CoreReset = 0,
+    /// `MemoryManagement` exception (VC_MMERR)
+    #[cfg(not(any(armv6m, armv8m_base)))]
+    MemoryManagement = 4,
+    /// `UsageFault` caused by an access to a coprocessor (VC_NOCPERR)
+    #[cfg(not(any(armv6m, armv8m_base)))]
+    NoCoprocessor = 5,
+    /// `UsageFault` caused by a checking error (VC_CHKERR)
+    #[cfg(not(any(armv6m, armv8m_base)))]
+    CheckingError = 6,
+    /// `UsageFault` caused by a state information error (VC_STATERR)
+    #[cfg(not(any(armv6m, armv8m_base)))]
+    StateError = 7,
+    /// `BusFault` (VC_BUSERR)
+    #[cfg(not(any(armv6m, armv8m_base)))]
+    BusFault = 8,
+    /// Fault during exception entry or return (VC_INTERR)
+    #[cfg(not(any(armv6m, armv8m_base)))]
+    ExceptionError = 9,
+    /// `HardFault` (VC_HARDERR)
+    HardFault = 10,
+    /// `SecureFault` (VC_SFERR)
+    #[cfg(armv8m_main)]
+    SecureFault = 11,
+}
+
 impl DCB {
     /// Enables TRACE. This is for example required by the
     /// `peripheral::DWT` cycle counter to work properly.
@@ -42,6 +104,141 @@ impl DCB {
         }
     }
 
+    /// Enables the `DebugMonitor` exception (monitor mode debugging)
+    ///
+    /// Debug events, like breakpoints and DWT watchpoints, raise the `DebugMonitor` exception
+    /// instead of being ignored. While a debugger has enabled halting debug (see
+    /// [`DCB::is_debugger_attached`]) debug events halt the processor instead.
+    ///
+    /// Debug events occurring while the processor executes at a priority equal to or higher than
+    /// that of `DebugMonitor` are ignored, except for `BKPT` instructions which escalate to
+    /// `HardFault`.
+    ///
+    /// This method is not available on ARMv6-M and ARMv8-M Baseline chips.
+    #[cfg(all(not(armv6m), not(armv8m_base)))]
+    #[inline]
+    pub fn enable_debug_monitor(&mut self) {
+        // set bit 16 / MON_EN
+        unsafe {
+            self.demcr.modify(|w| w | DCB_DEMCR_MON_EN);
+        }
+    }
+
+    /// Disables the `DebugMonitor` exception. See `DCB::enable_debug_monitor()` for more details
+    #[cfg(all(not(armv6m), not(armv8m_base)))]
+    #[inline]
+    pub fn disable_debug_monitor(&mut self) {
+        // unset bit 16 / MON_EN
+        unsafe {
+            self.demcr.modify(|w| w & !DCB_DEMCR_MON_EN);
+        }
+    }
+
+    /// Returns `true` if the `DebugMonitor` exception is enabled
+    #[cfg(all(not(armv6m), not(armv8m_base)))]
+    #[inline]
+    pub fn is_debug_monitor_enabled() -> bool {
+        // NOTE(unsafe) atomic read with no side effects
+        unsafe { (*Self::PTR).demcr.read() & DCB_DEMCR_MON_EN != 0 }
+    }
+
+    /// Pends the `DebugMonitor` exception
+    ///
+    /// The exception is taken even if monitor mode debugging is disabled, and no bit is set in
+    /// the Debug Fault Status Register.
+    #[cfg(all(not(armv6m), not(armv8m_base)))]
+    #[inline]
+    pub fn pend_debug_monitor(&mut self) {
+        // set bit 17 / MON_PEND
+        unsafe {
+            self.demcr.modify(|w| w | DCB_DEMCR_MON_PEND);
+        }
+    }
+
+    /// Clears the pending state of the `DebugMonitor` exception
+    #[cfg(all(not(armv6m), not(armv8m_base)))]
+    #[inline]
+    pub fn unpend_debug_monitor(&mut self) {
+        // unset bit 17 / MON_PEND
+        unsafe {
+            self.demcr.modify(|w| w & !DCB_DEMCR_MON_PEND);
+        }
+    }
+
+    /// Returns `true` if the `DebugMonitor` exception is pending
+    #[cfg(all(not(armv6m), not(armv8m_base)))]
+    #[inline]
+    pub fn is_debug_monitor_pending() -> bool {
+        // NOTE(unsafe) atomic read with no side effects
+        unsafe { (*Self::PTR).demcr.read() & DCB_DEMCR_MON_PEND != 0 }
+    }
+
+    /// Enables or disables single-stepping in monitor mode
+    ///
+    /// While enabled, returning from the `DebugMonitor` handler executes a single instruction
+    /// before the `DebugMonitor` exception is taken again, with DFSR.HALTED set.
+    #[cfg(all(not(armv6m), not(armv8m_base)))]
+    #[inline]
+    pub fn set_monitor_step(&mut self, step: bool) {
+        // NOTE(unsafe) `&mut self` guarantees exclusive access to DEMCR
+        unsafe { Self::modify_monitor_step(step) }
+    }
+
+    /// Sets or unsets bit 18 / MON_STEP, without a `DCB` handle
+    ///
+    /// # Safety
+    ///
+    /// The read-modify-write of DEMCR must not race with another one, e.g. by running in a
+    /// critical section.
+    #[cfg(all(not(armv6m), not(armv8m_base)))]
+    #[inline]
+    pub(crate) unsafe fn modify_monitor_step(step: bool) {
+        unsafe {
+            (*Self::PTR).demcr.modify(|w| {
+                if step {
+                    w | DCB_DEMCR_MON_STEP
+                } else {
+                    w & !DCB_DEMCR_MON_STEP
+                }
+            });
+        }
+    }
+
+    /// Returns `true` if single-stepping in monitor mode is enabled
+    #[cfg(all(not(armv6m), not(armv8m_base)))]
+    #[inline]
+    pub fn is_monitor_step_enabled() -> bool {
+        // NOTE(unsafe) atomic read with no side effects
+        unsafe { (*Self::PTR).demcr.read() & DCB_DEMCR_MON_STEP != 0 }
+    }
+
+    /// Sets or clears the MON_REQ bit
+    ///
+    /// The processor does not use this bit: it is available to the debug monitor software, for
+    /// example to record why the `DebugMonitor` exception was pended.
+    #[cfg(all(not(armv6m), not(armv8m_base)))]
+    #[inline]
+    pub fn set_monitor_request(&mut self, request: bool) {
+        // set or unset bit 19 / MON_REQ
+        unsafe {
+            self.demcr.modify(|w| {
+                if request {
+                    w | DCB_DEMCR_MON_REQ
+                } else {
+                    w & !DCB_DEMCR_MON_REQ
+                }
+            });
+        }
+    }
+
+    /// Returns the value of the MON_REQ bit. See `DCB::set_monitor_request()` for more details
+    #[cfg(all(not(armv6m), not(armv8m_base)))]
+    #[inline]
+    pub fn monitor_request() -> bool {
+        // NOTE(unsafe) atomic read with no side effects
+        unsafe { (*Self::PTR).demcr.read() & DCB_DEMCR_MON_REQ != 0 }
+    }
+
     /// Is there a debugger attached? (see note)
     ///
     /// Note: This function is [reported not to
@@ -57,4 +254,64 @@ impl DCB {
             value & 0x1 == 1
         }
     }
+
+    /// Reads the processor status from DHCSR
+    ///
+    /// Reading DHCSR clears the sticky `retired` and `reset` flags.
+    ///
+    /// Note: see [`DCB::is_debugger_attached`] about software access to DHCSR on Cortex-M0
+    /// devices.
+    #[inline]
+    pub fn debug_status() -> DebugStatus {
+        // NOTE(unsafe) atomic read; clearing the sticky bits is the documented side effect
+        let dhcsr = unsafe { (*Self::PTR).dhcsr.read() };
+        DebugStatus {
+            halted: dhcsr & DCB_DHCSR_S_HALT != 0,
+            sleeping: dhcsr & DCB_DHCSR_S_SLEEP != 0,
+            locked_up: dhcsr & DCB_DHCSR_S_LOCKUP != 0,
+            retired: dhcsr & DCB_DHCSR_S_RETIRE_ST != 0,
+            reset: dhcsr & DCB_DHCSR_S_RESET_ST != 0,
+        }
+    }
+
+    /// Enables a vector catch: the processor halts on entry to the matching exception
+    ///
+    /// Vector catches only take effect while a debugger has enabled halting debug. With monitor
+    /// mode debugging, they are recorded in the DFSR but do not raise `DebugMonitor`.
+    #[inline]
+    pub fn enable_vector_catch(&mut self, catch: VectorCatch) {
+        unsafe {
+            self.demcr.modify(|w| w | (1 << catch as u8));
+        }
+    }
+
+    /// Disables a vector catch. See `DCB::enable_vector_catch()` for more details
+    #[inline]
+    pub fn disable_vector_catch(&mut self, catch: VectorCatch) {
+        unsafe {
+            self.demcr.modify(|w| w & !(1 << catch as u8));
+        }
+    }
+
+    /// Returns `true` if the vector catch is enabled
+    #[inline]
+    pub fn is_vector_catch_enabled(catch: VectorCatch) -> bool {
+        // NOTE(unsafe) atomic read with no side effects
+        unsafe { (*Self::PTR).demcr.read() & (1 << catch as u8) != 0 }
+    }
+
+    /// Reads the Debug Core Register Data Register
+    ///
+    /// Outside of core register transfers, DCRDR can be used as a mailbox between a debug monitor
+    /// and an attached debugger.
+    #[inline]
+    pub fn read_dcrdr(&self) -> u32 {
+        self.dcrdr.read()
+    }
+
+    /// Writes the Debug Core Register Data Register. See `DCB::read_dcrdr()` for more details
+    #[inline]
+    pub fn write_dcrdr(&mut self, value: u32) {
+        unsafe { self.dcrdr.write(value) }
+    }
 }
@@ -62,6 +62,60 @@ pub struct Comparator {
     reserved: u32,
 }
 
+/// Type of access matched by a watchpoint
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AccessType {
+    /// Match data reads
+    ReadOnly,
+    /// Match data writes
+    WriteOnly,
+    /// Match both data reads and data writes
+    ReadWrite,
+}
+
+/// Description of a data watchpoint
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Watchpoint {
+    /// First address of the watched region, must be aligned to `size`.
+    pub address: u32,
+    /// Size of the watched region in bytes, must be a power of two.
+    ///
+    /// On ARMv8-M a single comparator can only watch 1, 2 or 4 bytes.
+    pub size: u32,
+    /// Accesses that trigger the watchpoint.
+    pub access: AccessType,
+}
+
+/// Possible error values returned by the DWT comparator methods.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DwtError {
+    /// The comparator number must be between 0 and [`DWT::num_comp`] - 1.
+    ComparatorNumberTooBig,
+    /// The watched address must be aligned to the size of the watched region.
+    UnalignedAddress,
+    /// The size of the watched region is not a power of two, or is larger than what the
+    /// comparator supports.
+    UnsupportedSize,
+}
+
+// DWT FUNCTION register fields
+const FUNCTION_MATCHED: u32 = 1 << 24;
+#[cfg(not(armv8m))]
+mod function_consts {
+    pub const FUNCTION_WATCH_READ: u32 = 0b0101;
+    pub const FUNCTION_WATCH_WRITE: u32 = 0b0110;
+    pub const FUNCTION_WATCH_READ_WRITE: u32 = 0b0111;
+}
+#[cfg(armv8m)]
+mod function_consts {
+    pub const FUNCTION_MATCH_DADDR_READ_WRITE: u32 = 0b0100;
+    pub const FUNCTION_MATCH_DADDR_WRITE: u32 = 0b0101;
+    pub const FUNCTION_MATCH_DADDR_READ: u32 = 0b0110;
+    pub const FUNCTION_ACTION_DEBUG_EVENT: u32 = 0b01 << 4;
+    pub const FUNCTION_DATAVSIZE_OFFSET: u32 = 10;
+}
+use self::function_consts::*;
+
 // DWT CTRL register fields
 const NUMCOMP_OFFSET: u32 = 28;
 #[cfg(not(armv6m))]
@@ -270,4 +324,101 @@ impl DWT {
     pub fn set_fold_count(&mut self, count: u8) {
         unsafe { self.foldcnt.write(count as u32) }
     }
+
+    /// Configures `comparator` as a data watchpoint
+    ///
+    /// A matching access generates a debug event: it halts the processor if a debugger is
+    /// connected and halting debug is enabled, or raises the `DebugMonitor` exception if monitor
+    /// mode debugging is enabled (see [`DCB::enable_debug_monitor`]).
+    ///
+    /// On ARMv6-M and ARMv7-M, the watched region can be any power of two the comparator's mask
+    /// supports. On ARMv8-M, a single comparator watches at most one aligned word.
+    ///
+    /// This function is executed under a critical section to prevent having inconsistent results.
+    ///
+    /// [`DCB::enable_debug_monitor`]: crate::peripheral::DCB::enable_debug_monitor
+    #[inline]
+    pub fn set_watchpoint(
+        &mut self,
+        comparator: u8,
+        watchpoint: Watchpoint,
+    ) -> Result<(), DwtError> {
+        if comparator >= Self::num_comp() {
+            return Err(DwtError::ComparatorNumberTooBig);
+        }
+        if !watchpoint.size.is_power_of_two() {
+            return Err(DwtError::UnsupportedSize);
+        }
+        if watchpoint.address & (watchpoint.size - 1) != 0 {
+            return Err(DwtError::UnalignedAddress);
+        }
+
+        let c = &self.c[usize::from(comparator)];
+        let size_log2 = watchpoint.size.trailing_zeros();
+
+        #[cfg(not(armv8m))]
+        let function = match watchpoint.access {
+            AccessType::ReadOnly => FUNCTION_WATCH_READ,
+            AccessType::WriteOnly => FUNCTION_WATCH_WRITE,
+            AccessType::ReadWrite => FUNCTION_WATCH_READ_WRITE,
+        };
+
+        #[cfg(armv8m)]
+        let function = {
+            if size_log2 > 2 {
+                return Err(DwtError::UnsupportedSize);
+            }
+            let matching = match watchpoint.access {
+                AccessType::ReadOnly => FUNCTION_MATCH_DADDR_READ,
+                AccessType::WriteOnly => FUNCTION_MATCH_DADDR_WRITE,
+                AccessType::ReadWrite => FUNCTION_MATCH_DADDR_READ_WRITE,
+            };
+            matching | FUNCTION_ACTION_DEBUG_EVENT | (size_log2 << FUNCTION_DATAVSIZE_OFFSET)
+        };
+
+        crate::interrupt::free(|_| unsafe {
+            // Disable the comparator while it is being reprogrammed.
+            c.function.write(0);
+            c.comp.write(watchpoint.address);
+
+            #[cfg(not(armv8m))]
+            {
+                // The number of implemented mask bits is implementation defined; unsupported
+                // bits read back as zero.
+                c.mask.write(size_log2);
+                if c.mask.read() != size_log2 {
+                    c.mask.write(0);
+                    return Err(DwtError::UnsupportedSize);
+                }
+            }
+
+            c.function.write(function);
+            Ok(())
+        })
+    }
+
+    /// Disables `comparator`
+    #[inline]
+    pub fn clear_watchpoint(&mut self, comparator: u8) -> Result<(), DwtError> {
+        if comparator >= Self::num_comp() {
+            return Err(DwtError::ComparatorNumberTooBig);
+        }
+
+        unsafe { self.c[usize::from(comparator)].function.write(0) }
+        Ok(())
+    }
+
+    /// Returns `true` if `comparator` matched since the last call of this function
+    ///
+    /// *NOTE* The MATCHED flag is cleared by reading the comparator FUNCTION register, so only
+    /// the first call after a match returns `true`. Returns `false` for unimplemented comparators.
+    #[inline]
+    pub fn has_matched(comparator: u8) -> bool {
+        if comparator >= Self::num_comp() {
+            return false;
+        }
+
+        // NOTE(unsafe) atomic read; its only side effect is clearing MATCHED
+        unsafe { (*Self::PTR).c[usize::from(comparator)].function.read() & FUNCTION_MATCHED != 0 }
+    }
 }
@@ -0,0 +1,514 @@
+//! Embedded Trace Macrocell
+//!
+//! *NOTE* Not available on Armv6-M and Armv8-M Baseline.
+//!
+//! The ETM generates a compressed trace of the executed instructions. Cortex-M3 and Cortex-M4
+//! implement ETMv3.5; Cortex-M7 (`cm7` feature) and ARMv8-M Mainline processors implement ETMv4.
+//! The register block and the encoding of [`EtmEvent`] follow the selected architecture.
+//!
+//! The ETM is optional: whether it is implemented can be found in the CoreSight ROM table. Its
+//! output goes through the TPIU, to a parallel trace port or, at low bandwidth, to SWO.
+
+use volatile_register::{RO, RW, WO};
+
+use crate::peripheral::{ETM, TPIU};
+
+const ETM_LAR_KEY: u32 = 0xC5AC_CE55;
+const ETM_LSR_SLK: u32 = 1 << 1;
+
+/// Polls of the programming status before giving up
+const PROGRAMMING_TIMEOUT: u32 = 10_000;
+
+#[cfg(not(any(armv8m_main, feature = "cm7")))]
+mod consts {
+    pub const ETMCR_POWERDOWN: u32 = 1 << 0;
+    pub const ETMCR_STALL: u32 = 1 << 7;
+    pub const ETMCR_BRANCH_OUTPUT: u32 = 1 << 8;
+    pub const ETMCR_PROGRAMMING: u32 = 1 << 10;
+    pub const ETMCR_PORT_SELECTION: u32 = 1 << 11;
+    pub const ETMCR_CYCLE_ACCURATE: u32 = 1 << 12;
+    pub const ETMCR_TIMESTAMP: u32 = 1 << 28;
+    pub const ETMSR_PROGRAMMING: u32 = 1 << 1;
+    /// TraceEnable control 1: exclude mode, no address ranges, i.e. trace everything
+    pub const ETMTECR1_EXCLUDE: u32 = 1 << 24;
+}
+
+#[cfg(any(armv8m_main, feature = "cm7"))]
+mod consts {
+    pub const TRCPRGCTLR_EN: u32 = 1 << 0;
+    pub const TRCSTATR_IDLE: u32 = 1 << 0;
+    pub const TRCCONFIGR_BB: u32 = 1 << 3;
+    pub const TRCCONFIGR_CCI: u32 = 1 << 4;
+    pub const TRCCONFIGR_TS: u32 = 1 << 11;
+    pub const TRCSTALLCTLR_ISTALL: u32 = 1 << 8;
+    pub const TRCVICTLR_SSSTATUS: u32 = 1 << 9;
+    pub const TRCIDR3_CCITMIN_MASK: u32 = 0xfff;
+}
+
+use consts::*;
+
+/// Register block (ETMv3.5)
+#[cfg(not(any(armv8m_main, feature = "cm7")))]
+#[repr(C)]
+pub struct RegisterBlock {
+    /// Main Control
+    pub cr: RW<u32>,
+    /// Configuration Code
+    pub ccr: RO<u32>,
+    /// Trigger Event
+    pub trigger: RW<u32>,
+    reserved0: u32,
+    /// Status
+    pub sr: RW<u32>,
+    /// System Configuration
+    pub scr: RO<u32>,
+    reserved1: [u32; 2],
+    /// TraceEnable Event
+    pub teevr: RW<u32>,
+    /// TraceEnable Control 1
+    pub tecr1: RW<u32>,
+    reserved2: u32,
+    /// FIFOFULL Level
+    pub fflr: RW<u32>,
+    reserved3: [u32; 68],
+    /// Free-running Counter Reload Value
+    pub cntrldvr1: RW<u32>,
+    reserved4: [u32; 39],
+    /// Synchronization Frequency
+    pub syncfr: RW<u32>,
+    /// ID
+    pub idr: RO<u32>,
+    /// Configuration Code Extension
+    pub ccer: RO<u32>,
+    reserved5: u32,
+    /// TraceEnable Start/Stop EmbeddedICE Control
+    pub tesseicr: RW<u32>,
+    reserved6: u32,
+    /// Timestamp Event
+    pub tsevr: RW<u32>,
+    reserved7: u32,
+    /// CoreSight Trace ID
+    pub traceidr: RW<u32>,
+    reserved8: u32,
+    /// ID 2
+    pub idr2: RO<u32>,
+    reserved9: [u32; 66],
+    /// Power-Down Status
+    pub pdsr: RO<u32>,
+    reserved10: [u32; 762],
+    /// Integration Mode Control
+    pub itctrl: RW<u32>,
+    reserved11: [u32; 39],
+    /// Claim Tag Set
+    pub claimset: RW<u32>,
+    /// Claim Tag Clear
+    pub claimclr: RW<u32>,
+    reserved12: [u32; 2],
+    /// Lock Access
+    pub lar: WO<u32>,
+    /// Lock Status
+    pub lsr: RO<u32>,
+    /// Authentication Status
+    pub authstatus: RO<u32>,
+    reserved13: [u32; 4],
+    /// Device Type
+    pub devtype: RO<u32>,
+    /// Peripheral Identification 4 to 7, then 0 to 3
+    pub pidr: [RO<u32>; 8],
+    /// Component Identification 0 to 3
+    pub cidr: [RO<u32>; 4],
+}
+
+/// Register block (ETMv4)
+#[cfg(any(armv8m_main, feature = "cm7"))]
+#[repr(C)]
+pub struct RegisterBlock {
+    reserved0: u32,
+    /// Programming Control
+    pub prgctlr: RW<u32>,
+    reserved1: u32,
+    /// Status
+    pub statr: RO<u32>,
+    /// Trace Configuration
+    pub configr: RW<u32>,
+    reserved2: u32,
+    /// Auxiliary Control
+    pub auxctlr: RW<u32>,
+    reserved3: u32,
+    /// Event Control 0
+    pub eventctl0r: RW<u32>,
+    /// Event Control 1
+    pub eventctl1r: RW<u32>,
+    reserved4: u32,
+    /// Stall Control
+    pub stallctlr: RW<u32>,
+    /// Global Timestamp Control
+    pub tsctlr: RW<u32>,
+    /// Synchronization Period
+    pub syncpr: RW<u32>,
+    /// Cycle Count Control
+    pub ccctlr: RW<u32>,
+    /// Branch Broadcast Control
+    pub bbctlr: RW<u32>,
+    /// Trace ID
+    pub traceidr: RW<u32>,
+    reserved5: [u32; 15],
+    /// ViewInst Main Control
+    pub victlr: RW<u32>,
+    /// ViewInst Include/Exclude Control
+    pub viiectlr: RW<u32>,
+    /// ViewInst Start/Stop Control
+    pub vissctlr: RW<u32>,
+    /// ViewInst Start/Stop PE Comparator Control
+    pub vipcssctlr: RW<u32>,
+    reserved6: [u32; 60],
+    /// ID 8 to 13
+    pub idr8: [RO<u32>; 6],
+    reserved7: [u32; 18],
+    /// ID 0 to 7
+    pub idr: [RO<u32>; 8],
+    /// Resource Selection Control
+    pub rsctlr: [RW<u32>; 32],
+    reserved8: [u32; 32],
+    /// OS Lock Access
+    pub oslar: WO<u32>,
+    /// OS Lock Status
+    pub oslsr: RO<u32>,
+    reserved9: [u32; 2],
+    /// Power Down Control
+    pub pdcr: RW<u32>,
+    /// Power Down Status
+    pub pdsr: RO<u32>,
+    reserved10: [u32; 762],
+    /// Integration Mode Control
+    pub itctrl: RW<u32>,
+    reserved11: [u32; 39],
+    /// Claim Tag Set
+    pub claimset: RW<u32>,
+    /// Claim Tag Clear
+    pub claimclr: RW<u32>,
+    reserved12: [u32; 2],
+    /// Lock Access
+    pub lar: WO<u32>,
+    /// Lock Status
+    pub lsr: RO<u32>,
+    /// Authentication Status
+    pub authstatus: RO<u32>,
+    /// Device Architecture
+    pub devarch: RO<u32>,
+    reserved13: [u32; 2],
+    /// Device Configuration
+    pub devid: RO<u32>,
+    /// Device Type
+    pub devtype: RO<u32>,
+    /// Peripheral Identification 4 to 7, then 0 to 3
+    pub pidr: [RO<u32>; 8],
+    /// Component Identification 0 to 3
+    pub cidr: [RO<u32>; 4],
+}
+
+/// An ETM event, which controls when tracing is enabled or triggered
+///
+/// Events are encoded differently by ETMv3.5 and ETMv4. Besides the constant events, an event
+/// can be built from its raw encoding, e.g. to select a counter or an address comparator.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct EtmEvent(u32);
+
+impl EtmEvent {
+    /// Always true (ETMv3.5: hard-wired resource 0x6f)
+    #[cfg(not(any(armv8m_main, feature = "cm7")))]
+    pub const ALWAYS: Self = EtmEvent(0x6f);
+    /// Never true (ETMv3.5: NOT of the hard-wired resource)
+    #[cfg(not(any(armv8m_main, feature = "cm7")))]
+    pub const NEVER: Self = EtmEvent(0x406f);
+    /// Always true (ETMv4: resource selector 1)
+    #[cfg(any(armv8m_main, feature = "cm7"))]
+    pub const ALWAYS: Self = EtmEvent(0x01);
+    /// Never true (ETMv4: resource selector 0)
+    #[cfg(any(armv8m_main, feature = "cm7"))]
+    pub const NEVER: Self = EtmEvent(0x00);
+
+    /// True when DWT comparator `comparator` matches (ETMv3.5 EmbeddedICE watchpoint resource)
+    ///
+    /// The DWT comparator function must be set to generate an ETM trigger.
+    #[cfg(not(any(armv8m_main, feature = "cm7")))]
+    #[inline]
+    pub const fn dwt_comparator(comparator: u8) -> Self {
+        EtmEvent(0b010 << 4 | (comparator as u32 & 0xf))
+    }
+
+    /// Creates an event from its raw encoding
+    #[inline]
+    pub const fn from_bits(bits: u32) -> Self {
+        EtmEvent(bits)
+    }
+
+    /// Returns the raw encoding of the event
+    #[inline]
+    pub const fn bits(self) -> u32 {
+        self.0
+    }
+}
+
+/// ETM configuration, see [`ETM::configure`]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct EtmSettings {
+    /// Identifier of the ETM on the trace bus
+    ///
+    /// Must be in range `0x01..=0x6f`, and differ from the ITM trace bus ID.
+    pub trace_id: u8,
+    /// Outputs the target address of every branch, instead of only the indirect ones
+    ///
+    /// This lets a decoder follow the execution without access to the program image.
+    pub branch_broadcast: bool,
+    /// Traces the number of cycles taken by instructions
+    pub cycle_accurate: bool,
+    /// Inserts timestamps in the trace
+    pub timestamps: bool,
+    /// Stalls the processor instead of losing trace when the ETM FIFO is full
+    pub stall: bool,
+    /// Event which enables tracing
+    pub trace_enable: EtmEvent,
+    /// Event which triggers a trace capture device
+    pub trigger: EtmEvent,
+}
+
+impl Default for EtmSettings {
+    /// Traces all instructions with trace ID 2, without branch broadcasting, cycle counts,
+    /// timestamps or trigger
+    #[inline]
+    fn default() -> Self {
+        EtmSettings {
+            trace_id: 0x02,
+            branch_broadcast: false,
+            cycle_accurate: false,
+            timestamps: false,
+            stall: false,
+            trace_enable: EtmEvent::ALWAYS,
+            trigger: EtmEvent::NEVER,
+        }
+    }
+}
+
+/// Possible errors of the ETM configuration methods
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum EtmError {
+    /// The trace ID is out of range `0x01..=0x6f`
+    InvalidTraceId,
+    /// The ETM did not acknowledge the start or the end of programming, e.g. because its clock
+    /// or power domain is off
+    NotResponding,
+}
+
+impl ETM {
+    /// Removes the software lock on the ETM registers, if implemented
+    #[inline]
+    pub fn unlock(&mut self) {
+        unsafe { self.lar.write(ETM_LAR_KEY) }
+    }
+
+    /// Returns `true` if the ETM registers are locked against writes
+    #[inline]
+    pub fn is_locked() -> bool {
+        // NOTE(unsafe) atomic read with no side effects
+        unsafe { (*Self::PTR).lsr.read() & ETM_LSR_SLK != 0 }
+    }
+
+    /// Returns the major and minor ETM architecture version, e.g. `(3, 5)` for ETMv3.5
+    #[cfg(not(any(armv8m_main, feature = "cm7")))]
+    #[inline]
+    pub fn architecture() -> (u8, u8) {
+        // NOTE(unsafe) atomic read with no side effects
+        let idr = unsafe { (*Self::PTR).idr.read() };
+        // the major version field is 2 for ETMv3
+        (((idr >> 8) & 0xf) as u8 + 1, ((idr >> 4) & 0xf) as u8)
+    }
+
+    /// Returns the major and minor ETM architecture version, e.g. `(4, 2)` for ETMv4.2
+    #[cfg(any(armv8m_main, feature = "cm7"))]
+    #[inline]
+    pub fn architecture() -> (u8, u8) {
+        // NOTE(unsafe) atomic read with no side effects
+        let idr1 = unsafe { (*Self::PTR).idr[1].read() };
+        (((idr1 >> 8) & 0xf) as u8, ((idr1 >> 4) & 0xf) as u8)
+    }
+
+    /// Returns the identifier of the ETM on the trace bus
+    #[inline]
+    pub fn trace_id() -> u8 {
+        // NOTE(unsafe) atomic read with no side effects
+        unsafe { ((*Self::PTR).traceidr.read() & 0x7f) as u8 }
+    }
+
+    /// Returns `true` if the ETM is tracing
+    #[cfg(not(any(armv8m_main, feature = "cm7")))]
+    #[inline]
+    pub fn is_enabled() -> bool {
+        // NOTE(unsafe) atomic read with no side effects
+        let cr = unsafe { (*Self::PTR).cr.read() };
+        cr & (ETMCR_POWERDOWN | ETMCR_PROGRAMMING) == 0
+    }
+
+    /// Returns `true` if the ETM is tracing
+    #[cfg(any(armv8m_main, feature = "cm7"))]
+    #[inline]
+    pub fn is_enabled() -> bool {
+        // NOTE(unsafe) atomic read with no side effects
+        unsafe { (*Self::PTR).prgctlr.read() & TRCPRGCTLR_EN != 0 }
+    }
+
+    /// Stops tracing and powers down the ETM
+    #[cfg(not(any(armv8m_main, feature = "cm7")))]
+    #[inline]
+    pub fn disable(&mut self) {
+        unsafe { self.cr.modify(|r| r | ETMCR_PROGRAMMING | ETMCR_POWERDOWN) }
+    }
+
+    /// Stops tracing
+    #[cfg(any(armv8m_main, feature = "cm7"))]
+    #[inline]
+    pub fn disable(&mut self) {
+        unsafe { self.prgctlr.write(0) }
+    }
+
+    /// Applies `settings` to the ETM and starts tracing
+    ///
+    /// The ETM is unlocked and put in programming mode while it is reconfigured. The TPIU
+    /// formatter is enabled, so the ETM trace can be told apart from the ITM trace by its trace
+    /// ID; the TPIU output itself must be set up separately, e.g. with `TPIU::set_protocol`. The
+    /// `DCB::enable_trace()` method must have been called before.
+    #[cfg(not(any(armv8m_main, feature = "cm7")))]
+    #[inline]
+    pub fn configure(&mut self, settings: EtmSettings, tpiu: &mut TPIU) -> Result<(), EtmError> {
+        if !(0x01..=0x6f).contains(&settings.trace_id) {
+            return Err(EtmError::InvalidTraceId);
+        }
+
+        self.unlock();
+        unsafe {
+            // power up in programming mode
+            self.cr.write(ETMCR_PROGRAMMING);
+        }
+        self.wait_programming(true)?;
+
+        let mut cr = ETMCR_PROGRAMMING | ETMCR_PORT_SELECTION;
+        if settings.branch_broadcast {
+            cr |= ETMCR_BRANCH_OUTPUT;
+        }
+        if settings.cycle_accurate {
+            cr |= ETMCR_CYCLE_ACCURATE;
+        }
+        if settings.timestamps {
+            cr |= ETMCR_TIMESTAMP;
+        }
+        if settings.stall {
+            cr |= ETMCR_STALL;
+        }
+
+        unsafe {
+            self.cr.write(cr);
+            self.traceidr.write(u32::from(settings.trace_id));
+            self.trigger.write(settings.trigger.bits());
+            self.teevr.write(settings.trace_enable.bits());
+            self.tecr1.write(ETMTECR1_EXCLUDE);
+            self.tsevr.write(if settings.timestamps {
+                EtmEvent::ALWAYS.bits()
+            } else {
+                EtmEvent::NEVER.bits()
+            });
+        }
+
+        tpiu.unlock();
+        tpiu.set_formatter(true);
+
+        unsafe { self.cr.write(cr & !ETMCR_PROGRAMMING) };
+        self.wait_programming(false)
+    }
+
+    /// Applies `settings` to the ETM and starts tracing
+    ///
+    /// The ETM is unlocked and stopped while it is reconfigured. The TPIU formatter is enabled,
+    /// so the ETM trace can be told apart from the ITM trace by its trace ID; the TPIU output
+    /// itself must be set up separately, e.g. with `TPIU::set_protocol`. The
+    /// `DCB::enable_trace()` method must have been called before.
+    #[cfg(any(armv8m_main, feature = "cm7"))]
+    #[inline]
+    pub fn configure(&mut self, settings: EtmSettings, tpiu: &mut TPIU) -> Result<(), EtmError> {
+        if !(0x01..=0x6f).contains(&settings.trace_id) {
+            return Err(EtmError::InvalidTraceId);
+        }
+
+        self.unlock();
+        unsafe {
+            // release the OS lock, which blocks programming
+            self.oslar.write(0);
+            self.prgctlr.write(0);
+        }
+        self.wait_idle(true)?;
+
+        let mut config = 0;
+        if settings.branch_broadcast {
+            config |= TRCCONFIGR_BB;
+        }
+        if settings.cycle_accurate {
+            config |= TRCCONFIGR_CCI;
+        }
+        if settings.timestamps {
+            config |= TRCCONFIGR_TS;
+        }
+
+        unsafe {
+            self.configr.write(config);
+            self.traceidr.write(u32::from(settings.trace_id));
+            // branch broadcasting over the whole address space
+            self.bbctlr.write(0);
+            // smallest cycle count threshold
+            self.ccctlr.write(self.idr[3].read() & TRCIDR3_CCITMIN_MASK);
+            self.tsctlr.write(if settings.timestamps {
+                EtmEvent::ALWAYS.bits()
+            } else {
+                EtmEvent::NEVER.bits()
+            });
+            self.stallctlr.write(if settings.stall {
+                TRCSTALLCTLR_ISTALL
+            } else {
+                0
+            });
+            self.eventctl0r.write(settings.trigger.bits());
+            self.eventctl1r.write(0);
+            // trace all addresses while `trace_enable` is true
+            self.victlr
+                .write(TRCVICTLR_SSSTATUS | (settings.trace_enable.bits() & 0xff));
+            self.viiectlr.write(0);
+            self.vissctlr.write(0);
+        }
+
+        tpiu.unlock();
+        tpiu.set_formatter(true);
+
+        unsafe { self.prgctlr.write(TRCPRGCTLR_EN) };
+        self.wait_idle(false)
+    }
+
+    #[cfg(not(any(armv8m_main, feature = "cm7")))]
+    #[inline]
+    fn wait_programming(&self, programming: bool) -> Result<(), EtmError> {
+        for _ in 0..PROGRAMMING_TIMEOUT {
+            if (self.sr.read() & ETMSR_PROGRAMMING != 0) == programming {
+                return Ok(());
+            }
+        }
+        Err(EtmError::NotResponding)
+    }
+
+    #[cfg(any(armv8m_main, feature = "cm7"))]
+    #[inline]
+    fn wait_idle(&self, idle: bool) -> Result<(), EtmError> {
+        for _ in 0..PROGRAMMING_TIMEOUT {
+            if (self.statr.read() & TRCSTATR_IDLE != 0) == idle {
+                return Ok(());
+            }
+        }
+        Err(EtmError::NotResponding)
+    }
+}
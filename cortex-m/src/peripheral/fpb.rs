@@ -1,9 +1,20 @@
 //! Flash Patch and Breakpoint unit
 //!
 //! *NOTE* Not available on Armv6-M.
+//!
+//! The FPB implements hardware breakpoints and, on FPBv1, can remap instruction and literal
+//! fetches from the code region to a table in SRAM ("flash patching").
+//!
+//! Comparators are numbered from zero: the first [`FPB::num_code_comparators`] comparators are
+//! instruction address comparators, followed by [`FPB::num_literal_comparators`] literal address
+//! comparators.
+
+use core::cell::UnsafeCell;
 
 use volatile_register::{RO, RW, WO};
 
+use crate::peripheral::FPB;
+
 /// Register block
 #[repr(C)]
 pub struct RegisterBlock {
@@ -19,3 +30,324 @@ pub struct RegisterBlock {
     /// Lock Status
     pub lsr: RO<u32>,
 }
+
+/// Encoding of the FPB comparators
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FpbRevision {
+    /// FPBv1: breakpoints and remapping restricted to the code region (`0x0000_0000` to
+    /// `0x1FFF_FFFF`), breakpoints select a halfword through the REPLACE field.
+    V1,
+    /// FPBv2: breakpoints anywhere in the address space, no remapping.
+    V2,
+    /// An unknown revision, reported as the raw REV field.
+    Unknown(u8),
+}
+
+/// Possible error values returned by the FPB methods.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FpbError {
+    /// The comparator number must be lower than the number of implemented comparators of the
+    /// requested kind.
+    ComparatorNumberTooBig,
+    /// The address can not be matched by the comparator: it is outside of the code region on
+    /// FPBv1, or it is not word aligned for a remapped word.
+    UnsupportedAddress,
+    /// The FPB does not support remapping (FPBv2, or FP_REMAP.RMPSPT is clear).
+    RemapNotSupported,
+    /// The remap table must be located in the SRAM region (`0x2000_0000` to `0x3FFF_FFFF`).
+    UnsupportedRemapTable,
+}
+
+// FPBv1 implements at most 6 instruction and 2 literal comparators.
+const REMAP_TABLE_ENTRIES: usize = 8;
+
+/// Table of replacement words used by FPBv1 remapping
+///
+/// Entry `n` of the table replaces the word matched by comparator `n`. The table is 32-byte
+/// aligned and must be located in the SRAM region.
+#[repr(C, align(32))]
+pub struct RemapTable {
+    entries: [UnsafeCell<u32>; REMAP_TABLE_ENTRIES],
+}
+
+unsafe impl Sync for RemapTable {}
+
+impl RemapTable {
+    /// Const-initialise an empty remap table
+    #[inline]
+    pub const fn new() -> Self {
+        RemapTable {
+            entries: [const { UnsafeCell::new(0) }; REMAP_TABLE_ENTRIES],
+        }
+    }
+}
+
+impl Default for RemapTable {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// FPB CTRL register fields
+const CTRL_ENABLE: u32 = 1 << 0;
+const CTRL_KEY: u32 = 1 << 1;
+const CTRL_NUM_CODE_LOW_OFFSET: u32 = 4;
+const CTRL_NUM_LIT_OFFSET: u32 = 8;
+const CTRL_NUM_CODE_HIGH_OFFSET: u32 = 12;
+const CTRL_REV_OFFSET: u32 = 28;
+
+// FPB REMAP register fields
+const REMAP_RMPSPT: u32 = 1 << 29;
+const REMAP_ADDRESS_MASK: u32 = 0x1FFF_FFE0;
+const SRAM_REGION: u32 = 0x2000_0000;
+
+// FPB COMP register fields
+const COMP_ENABLE: u32 = 1 << 0;
+const COMP_V1_ADDRESS_MASK: u32 = 0x1FFF_FFFC;
+const COMP_V1_REPLACE_REMAP: u32 = 0b00 << 30;
+const COMP_V1_REPLACE_LOWER: u32 = 0b01 << 30;
+const COMP_V1_REPLACE_UPPER: u32 = 0b10 << 30;
+const CODE_REGION_END: u32 = 0x2000_0000;
+
+impl FPB {
+    /// Returns the number of instruction address comparators
+    #[inline]
+    pub fn num_code_comparators() -> u8 {
+        // NOTE(unsafe) atomic read with no side effects
+        let ctrl = unsafe { (*Self::PTR).ctrl.read() };
+        let low = (ctrl >> CTRL_NUM_CODE_LOW_OFFSET) & 0xF;
+        let high = (ctrl >> CTRL_NUM_CODE_HIGH_OFFSET) & 0x7;
+        ((high << 4) | low) as u8
+    }
+
+    /// Returns the number of literal address comparators
+    #[inline]
+    pub fn num_literal_comparators() -> u8 {
+        // NOTE(unsafe) atomic read with no side effects
+        unsafe { (((*Self::PTR).ctrl.read() >> CTRL_NUM_LIT_OFFSET) & 0xF) as u8 }
+    }
+
+    /// Returns the revision of the FPB, which determines the comparator encoding
+    #[inline]
+    pub fn revision() -> FpbRevision {
+        // NOTE(unsafe) atomic read with no side effects
+        match unsafe { (*Self::PTR).ctrl.read() >> CTRL_REV_OFFSET } {
+            0 => FpbRevision::V1,
+            1 => FpbRevision::V2,
+            rev => FpbRevision::Unknown(rev as u8),
+        }
+    }
+
+    /// Returns `true` if the FPB supports remapping to a [`RemapTable`]
+    #[inline]
+    pub fn has_remap() -> bool {
+        // NOTE(unsafe) atomic read with no side effects
+        Self::revision() == FpbRevision::V1
+            && unsafe { (*Self::PTR).remap.read() } & REMAP_RMPSPT != 0
+    }
+
+    /// Enables the FPB
+    #[inline]
+    pub fn enable(&mut self) {
+        // Writes to CTRL are ignored unless KEY is set.
+        unsafe { self.ctrl.write(CTRL_KEY | CTRL_ENABLE) }
+    }
+
+    /// Disables the FPB
+    ///
+    /// The comparators keep their configuration but stop matching.
+    #[inline]
+    pub fn disable(&mut self) {
+        unsafe { self.ctrl.write(CTRL_KEY) }
+    }
+
+    /// Returns `true` if the FPB is enabled
+    #[inline]
+    pub fn is_enabled() -> bool {
+        // NOTE(unsafe) atomic read with no side effects
+        unsafe { (*Self::PTR).ctrl.read() & CTRL_ENABLE != 0 }
+    }
+
+    /// Removes the software lock on the FPB
+    ///
+    /// Some devices, like the STM32F7, software lock the FPB after a power cycle.
+    #[inline]
+    pub fn unlock() {
+        // NOTE(unsafe) atomic write to a stateless, write-only register
+        unsafe { (*Self::PTR).lar.write(0xC5AC_CE55) }
+    }
+
+    /// Sets a hardware breakpoint on the instruction at `address`
+    ///
+    /// `comparator` must be an instruction address comparator. The Thumb bit (bit 0) of
+    /// `address` is ignored, so function pointers can be used directly.
+    ///
+    /// Executing the instruction generates a debug event: it halts the processor if a debugger
+    /// is connected, or raises the `DebugMonitor` exception if monitor mode debugging is enabled
+    /// (see [`DCB::enable_debug_monitor`]). Otherwise the breakpoint escalates to `HardFault`.
+    ///
+    /// The FPB itself must be enabled with [`FPB::enable`] for breakpoints to match.
+    ///
+    /// [`DCB::enable_debug_monitor`]: crate::peripheral::DCB::enable_debug_monitor
+    #[inline]
+    pub fn set_breakpoint(&mut self, comparator: u8, address: u32) -> Result<(), FpbError> {
+        if comparator >= Self::num_code_comparators() {
+            return Err(FpbError::ComparatorNumberTooBig);
+        }
+
+        let comp = match Self::revision() {
+            FpbRevision::V1 => {
+                if address >= CODE_REGION_END {
+                    return Err(FpbError::UnsupportedAddress);
+                }
+
+                // FPBv1 compares word addresses and selects the halfword to break on with
+                // the REPLACE field.
+                let replace = if address & 0b10 == 0 {
+                    COMP_V1_REPLACE_LOWER
+                } else {
+                    COMP_V1_REPLACE_UPPER
+                };
+                replace | (address & COMP_V1_ADDRESS_MASK) | COMP_ENABLE
+            }
+            _ => (address & !0b1) | COMP_ENABLE,
+        };
+
+        unsafe { Self::comparator(comparator)?.write(comp) }
+        Ok(())
+    }
+
+    /// Disables `comparator`
+    ///
+    /// This removes a breakpoint or a remapping set on this comparator. `comparator` can be an
+    /// instruction or a literal address comparator.
+    #[inline]
+    pub fn clear_comparator(&mut self, comparator: u8) -> Result<(), FpbError> {
+        if comparator >= Self::num_code_comparators() + Self::num_literal_comparators() {
+            return Err(FpbError::ComparatorNumberTooBig);
+        }
+
+        unsafe { Self::comparator(comparator)?.write(0) }
+        Ok(())
+    }
+
+    /// Returns `true` if `comparator` is enabled
+    #[inline]
+    pub fn is_comparator_enabled(comparator: u8) -> bool {
+        if comparator >= Self::num_code_comparators() + Self::num_literal_comparators() {
+            return false;
+        }
+
+        Self::comparator(comparator).is_ok_and(|comp| comp.read() & COMP_ENABLE != 0)
+    }
+
+    /// Selects the remap table used by remapping comparators (FPBv1 only)
+    #[inline]
+    pub fn set_remap_table(&mut self, table: &'static RemapTable) -> Result<(), FpbError> {
+        if !Self::has_remap() {
+            return Err(FpbError::RemapNotSupported);
+        }
+
+        let address = table as *const RemapTable as u32;
+        if address & !REMAP_ADDRESS_MASK != SRAM_REGION {
+            return Err(FpbError::UnsupportedRemapTable);
+        }
+
+        unsafe { self.remap.write(address & REMAP_ADDRESS_MASK) }
+        Ok(())
+    }
+
+    /// Replaces the instruction word at `address` with `word` (FPBv1 only)
+    ///
+    /// `comparator` must be an instruction address comparator, and `address` a word-aligned
+    /// address in the code region. The replacement word is stored in the remap table selected
+    /// with [`FPB::set_remap_table`].
+    ///
+    /// # Safety
+    ///
+    /// This changes the code executed by the processor. The replacement must be valid code in
+    /// every context the patched instructions can be executed in.
+    #[inline]
+    pub unsafe fn patch_instruction(
+        &mut self,
+        comparator: u8,
+        address: u32,
+        word: u32,
+    ) -> Result<(), FpbError> {
+        if comparator >= Self::num_code_comparators() {
+            return Err(FpbError::ComparatorNumberTooBig);
+        }
+
+        unsafe { self.remap_word(comparator, address, word) }
+    }
+
+    /// Replaces the literal word at `address` with `word` (FPBv1 only)
+    ///
+    /// `literal` is the index of the literal comparator, between 0 and
+    /// [`FPB::num_literal_comparators`] - 1, and `address` a word-aligned address in the code
+    /// region. The replacement word is stored in the remap table selected with
+    /// [`FPB::set_remap_table`].
+    ///
+    /// # Safety
+    ///
+    /// This changes the constants read by the processor. The replacement must be valid in every
+    /// context the patched literal can be read in.
+    #[inline]
+    pub unsafe fn patch_literal(
+        &mut self,
+        literal: u8,
+        address: u32,
+        word: u32,
+    ) -> Result<(), FpbError> {
+        if literal >= Self::num_literal_comparators() {
+            return Err(FpbError::ComparatorNumberTooBig);
+        }
+
+        let comparator = Self::num_code_comparators() + literal;
+        unsafe { self.remap_word(comparator, address, word) }
+    }
+
+    /// Returns the COMP register of `comparator`
+    ///
+    /// The NUM_CODE and NUM_LIT fields can report more comparators than there are registers.
+    #[inline]
+    fn comparator(comparator: u8) -> Result<&'static RW<u32>, FpbError> {
+        // NOTE(unsafe) the register block is valid for the whole program
+        unsafe { &(*Self::PTR).comp }
+            .get(usize::from(comparator))
+            .ok_or(FpbError::ComparatorNumberTooBig)
+    }
+
+    #[inline]
+    unsafe fn remap_word(
+        &mut self,
+        comparator: u8,
+        address: u32,
+        word: u32,
+    ) -> Result<(), FpbError> {
+        if !Self::has_remap() {
+            return Err(FpbError::RemapNotSupported);
+        }
+        if usize::from(comparator) >= REMAP_TABLE_ENTRIES {
+            return Err(FpbError::ComparatorNumberTooBig);
+        }
+        if address >= CODE_REGION_END || address & 0b11 != 0 {
+            return Err(FpbError::UnsupportedAddress);
+        }
+
+        let table = (self.remap.read() & REMAP_ADDRESS_MASK) | SRAM_REGION;
+        let entry = (table as *mut u32).wrapping_add(usize::from(comparator));
+
+        unsafe {
+            // Disable the comparator while its replacement word is updated.
+            self.comp[usize::from(comparator)].write(0);
+            core::ptr::write_volatile(entry, word);
+            crate::asm::dsb();
+            self.comp[usize::from(comparator)].write(COMP_V1_REPLACE_REMAP | address | COMP_ENABLE);
+        }
+        crate::asm::isb();
+
+        Ok(())
+    }
+}
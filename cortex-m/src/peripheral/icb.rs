@@ -10,8 +10,8 @@ pub struct RegisterBlock {
     /// Interrupt Controller Type Register
     ///
     /// The bottom four bits of this register give the number of implemented
-    /// interrupt lines, divided by 32. So a value of `0b0010` indicates 64
-    /// interrupts.
+    /// interrupt lines, divided by 32, minus one. So a value of `0b0010`
+    /// indicates up to 96 interrupts.
     #[cfg(any(armv7m, armv8m, native))]
     pub ictr: RO<u32>,
 
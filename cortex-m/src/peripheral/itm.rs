@@ -7,6 +7,24 @@ use core::ptr;
 
 use volatile_register::{RO, RW, WO};
 
+use crate::peripheral::ITM;
+
+const ITM_TCR_ITMENA: u32 = 1 << 0;
+const ITM_TCR_TSENA: u32 = 1 << 1;
+const ITM_TCR_SYNCENA: u32 = 1 << 2;
+const ITM_TCR_TXENA: u32 = 1 << 3;
+const ITM_TCR_SWOENA: u32 = 1 << 4;
+const ITM_TCR_TSPRESCALE_OFFSET: u32 = 8;
+const ITM_TCR_TSPRESCALE_MASK: u32 = 0b11 << ITM_TCR_TSPRESCALE_OFFSET;
+const ITM_TCR_GTSFREQ_OFFSET: u32 = 10;
+const ITM_TCR_GTSFREQ_MASK: u32 = 0b11 << ITM_TCR_GTSFREQ_OFFSET;
+const ITM_TCR_TRACEBUSID_OFFSET: u32 = 16;
+const ITM_TCR_TRACEBUSID_MASK: u32 = 0x7f << ITM_TCR_TRACEBUSID_OFFSET;
+const ITM_TCR_BUSY: u32 = 1 << 23;
+
+const ITM_LAR_KEY: u32 = 0xC5AC_CE55;
+const ITM_LSR_SLK: u32 = 1 << 1;
+
 /// Register block
 #[repr(C)]
 pub struct RegisterBlock {
@@ -52,6 +70,21 @@ impl Stim {
         unsafe { ptr::write_volatile(self.register.get(), value) }
     }
 
+    /// Returns the number of the stimulus port
+    #[inline]
+    pub fn port_number(&self) -> u8 {
+        ((self as *const Stim as usize - ITM::PTR as usize) / 4) as u8
+    }
+
+    /// Returns `true` if writes to this stimulus port are emitted
+    ///
+    /// This requires both the ITM (ITMENA) and the stimulus port (TER) to be enabled. Writes to
+    /// a disabled port are discarded.
+    #[inline]
+    pub fn is_enabled(&self) -> bool {
+        ITM::is_enabled() && ITM::is_port_enabled(self.port_number())
+    }
+
     /// Returns `true` if the stimulus port is ready to accept more data
     #[cfg(not(armv8m))]
     #[inline]
@@ -69,3 +102,326 @@ impl Stim {
         unsafe { ptr::read_volatile(self.register.get()) & 0b11 != 0 }
     }
 }
+
+/// Local timestamp generation
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum LocalTimestampOptions {
+    /// Local timestamps are disabled
+    Disabled,
+    /// Local timestamps are counted at the timestamp clock frequency
+    Enabled,
+    /// Local timestamps are counted at the timestamp clock frequency divided by 4
+    EnabledDiv4,
+    /// Local timestamps are counted at the timestamp clock frequency divided by 16
+    EnabledDiv16,
+    /// Local timestamps are counted at the timestamp clock frequency divided by 64
+    EnabledDiv64,
+}
+
+/// Global timestamp generation (GTSFREQ)
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum GlobalTimestampOptions {
+    /// Global timestamps are disabled
+    Disabled = 0b00,
+    /// A global timestamp is generated about every 128 cycles
+    Every128Cycles = 0b01,
+    /// A global timestamp is generated about every 8192 cycles
+    Every8192Cycles = 0b10,
+    /// A global timestamp is generated after every packet, if the output FIFO is empty
+    EveryPacket = 0b11,
+}
+
+/// Clock source of the local timestamp counter (SWOENA)
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum TimestampClkSrc {
+    /// The processor clock
+    SystemClock,
+    /// The asynchronous clock of the TPIU
+    AsyncTPIU,
+}
+
+/// ITM configuration, see [`ITM::configure`]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct ItmSettings {
+    /// Enables the ITM (ITMENA)
+    pub enable: bool,
+    /// Local timestamp generation (TSENA and TSPrescale)
+    pub local_timestamps: LocalTimestampOptions,
+    /// Clock source of the local timestamp counter (SWOENA)
+    pub timestamp_clock: TimestampClkSrc,
+    /// Generates synchronization packets (SYNCENA)
+    ///
+    /// The DWT synchronization packet counter (`DWT::ctrl` SYNCTAP) sets their rate.
+    pub synchronization: bool,
+    /// Forwards DWT packets to the ITM (TXENA)
+    pub forward_dwt: bool,
+    /// Global timestamp generation (GTSFREQ)
+    pub global_timestamps: GlobalTimestampOptions,
+    /// Identifier of the ITM on the trace bus (TraceBusID)
+    ///
+    /// Must be in range `0x01..=0x6f` when set. Left unchanged when `None`.
+    pub bus_id: Option<u8>,
+    /// Stimulus ports 0 to 31 to enable, one bit per port (TER0)
+    pub enabled_ports: u32,
+    /// Stimulus ports that are only writable in privileged mode, one bit per group of 8 ports
+    /// (TPR), see [`ITM::set_port_privilege`]
+    pub privileged_ports: u32,
+}
+
+impl Default for ItmSettings {
+    /// ITM enabled without timestamps, with synchronization packets and stimulus port 0 enabled
+    #[inline]
+    fn default() -> Self {
+        ItmSettings {
+            enable: true,
+            local_timestamps: LocalTimestampOptions::Disabled,
+            timestamp_clock: TimestampClkSrc::SystemClock,
+            synchronization: true,
+            forward_dwt: false,
+            global_timestamps: GlobalTimestampOptions::Disabled,
+            bus_id: None,
+            enabled_ports: 1,
+            privileged_ports: 0,
+        }
+    }
+}
+
+/// Possible errors of the ITM configuration methods
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ItmError {
+    /// Global timestamps are not implemented
+    GlobalTimestampsNotSupported,
+    /// The local timestamp prescaler is not implemented
+    PrescalerNotSupported,
+    /// The selected timestamp clock source is not implemented
+    TimestampClkSrcNotSupported,
+    /// The trace bus ID is out of range `0x01..=0x6f`
+    InvalidBusId,
+}
+
+impl ITM {
+    /// Removes the software lock on the ITM registers, if implemented
+    #[inline]
+    pub fn unlock(&mut self) {
+        unsafe { self.lar.write(ITM_LAR_KEY) }
+    }
+
+    /// Returns `true` if the ITM registers are locked against writes
+    #[inline]
+    pub fn is_locked() -> bool {
+        // NOTE(unsafe) atomic read with no side effects
+        unsafe { (*Self::PTR).lsr.read() & ITM_LSR_SLK != 0 }
+    }
+
+    /// Enables the ITM (ITMENA)
+    #[inline]
+    pub fn enable(&mut self) {
+        unsafe { self.tcr.modify(|r| r | ITM_TCR_ITMENA) }
+    }
+
+    /// Disables the ITM. See `ITM::is_busy()` to know when pending packets have been emitted
+    #[inline]
+    pub fn disable(&mut self) {
+        unsafe { self.tcr.modify(|r| r & !ITM_TCR_ITMENA) }
+    }
+
+    /// Returns `true` if the ITM is enabled
+    #[inline]
+    pub fn is_enabled() -> bool {
+        // NOTE(unsafe) atomic read with no side effects
+        unsafe { (*Self::PTR).tcr.read() & ITM_TCR_ITMENA != 0 }
+    }
+
+    /// Returns `true` if the ITM is processing packets (BUSY)
+    #[inline]
+    pub fn is_busy() -> bool {
+        // NOTE(unsafe) atomic read with no side effects
+        unsafe { (*Self::PTR).tcr.read() & ITM_TCR_BUSY != 0 }
+    }
+
+    /// Configures local timestamp generation (TSENA and TSPrescale)
+    ///
+    /// Returns `Err(ItmError::PrescalerNotSupported)` if a prescaler was requested but is not
+    /// implemented.
+    #[inline]
+    pub fn set_local_timestamps(
+        &mut self,
+        timestamps: LocalTimestampOptions,
+    ) -> Result<(), ItmError> {
+        let (enable, prescale) = match timestamps {
+            LocalTimestampOptions::Disabled => (false, 0b00),
+            LocalTimestampOptions::Enabled => (true, 0b00),
+            LocalTimestampOptions::EnabledDiv4 => (true, 0b01),
+            LocalTimestampOptions::EnabledDiv16 => (true, 0b10),
+            LocalTimestampOptions::EnabledDiv64 => (true, 0b11),
+        };
+        let prescale = prescale << ITM_TCR_TSPRESCALE_OFFSET;
+        unsafe {
+            self.tcr.modify(|r| {
+                let r = (r & !ITM_TCR_TSPRESCALE_MASK) | prescale;
+                if enable {
+                    r | ITM_TCR_TSENA
+                } else {
+                    r & !ITM_TCR_TSENA
+                }
+            });
+        }
+        if self.tcr.read() & ITM_TCR_TSPRESCALE_MASK != prescale {
+            return Err(ItmError::PrescalerNotSupported);
+        }
+        Ok(())
+    }
+
+    /// Selects the clock source of the local timestamp counter (SWOENA)
+    ///
+    /// Returns `Err(ItmError::TimestampClkSrcNotSupported)` if the clock source is not
+    /// implemented.
+    #[inline]
+    pub fn set_timestamp_clock(&mut self, source: TimestampClkSrc) -> Result<(), ItmError> {
+        let swoena = match source {
+            TimestampClkSrc::SystemClock => 0,
+            TimestampClkSrc::AsyncTPIU => ITM_TCR_SWOENA,
+        };
+        unsafe { self.tcr.modify(|r| (r & !ITM_TCR_SWOENA) | swoena) }
+        if self.tcr.read() & ITM_TCR_SWOENA != swoena {
+            return Err(ItmError::TimestampClkSrcNotSupported);
+        }
+        Ok(())
+    }
+
+    /// Enables or disables the generation of synchronization packets (SYNCENA)
+    #[inline]
+    pub fn set_synchronization(&mut self, enable: bool) {
+        self.set_tcr_bit(ITM_TCR_SYNCENA, enable);
+    }
+
+    /// Enables or disables the forwarding of DWT packets to the ITM (TXENA)
+    #[inline]
+    pub fn set_dwt_forwarding(&mut self, enable: bool) {
+        self.set_tcr_bit(ITM_TCR_TXENA, enable);
+    }
+
+    /// Configures global timestamp generation (GTSFREQ)
+    ///
+    /// Returns `Err(ItmError::GlobalTimestampsNotSupported)` if global timestamps are not
+    /// implemented.
+    #[inline]
+    pub fn set_global_timestamps(
+        &mut self,
+        timestamps: GlobalTimestampOptions,
+    ) -> Result<(), ItmError> {
+        let gtsfreq = (timestamps as u32) << ITM_TCR_GTSFREQ_OFFSET;
+        unsafe { self.tcr.modify(|r| (r & !ITM_TCR_GTSFREQ_MASK) | gtsfreq) }
+        if self.tcr.read() & ITM_TCR_GTSFREQ_MASK != gtsfreq {
+            return Err(ItmError::GlobalTimestampsNotSupported);
+        }
+        Ok(())
+    }
+
+    /// Sets the identifier of the ITM on the trace bus (TraceBusID)
+    ///
+    /// Each trace source connected to the TPIU needs a unique identifier in range `0x01..=0x6f`.
+    #[inline]
+    pub fn set_trace_bus_id(&mut self, id: u8) -> Result<(), ItmError> {
+        if !(0x01..=0x6f).contains(&id) {
+            return Err(ItmError::InvalidBusId);
+        }
+        let id = u32::from(id) << ITM_TCR_TRACEBUSID_OFFSET;
+        unsafe { self.tcr.modify(|r| (r & !ITM_TCR_TRACEBUSID_MASK) | id) }
+        Ok(())
+    }
+
+    /// Returns the identifier of the ITM on the trace bus
+    #[inline]
+    pub fn trace_bus_id() -> u8 {
+        // NOTE(unsafe) atomic read with no side effects
+        let tcr = unsafe { (*Self::PTR).tcr.read() };
+        ((tcr & ITM_TCR_TRACEBUSID_MASK) >> ITM_TCR_TRACEBUSID_OFFSET) as u8
+    }
+
+    /// Enables a stimulus port
+    #[inline]
+    pub fn enable_port(&mut self, port: u8) {
+        let (register, bit) = Self::ter_position(port);
+        unsafe { self.ter[register].modify(|r| r | bit) }
+    }
+
+    /// Disables a stimulus port: writes to it are ignored
+    #[inline]
+    pub fn disable_port(&mut self, port: u8) {
+        let (register, bit) = Self::ter_position(port);
+        unsafe { self.ter[register].modify(|r| r & !bit) }
+    }
+
+    /// Returns `true` if the stimulus port is enabled
+    #[inline]
+    pub fn is_port_enabled(port: u8) -> bool {
+        let (register, bit) = Self::ter_position(port);
+        // NOTE(unsafe) atomic read with no side effects
+        unsafe { (*Self::PTR).ter[register].read() & bit != 0 }
+    }
+
+    /// Makes groups of 8 stimulus ports only writable in privileged mode
+    ///
+    /// Bit `n` of `mask` covers stimulus ports `8 * n` to `8 * n + 7`. Only the groups that are
+    /// implemented can be set.
+    #[inline]
+    pub fn set_port_privilege(&mut self, mask: u32) {
+        unsafe { self.tpr.write(mask) }
+    }
+
+    /// Returns the mask of groups of stimulus ports that are only writable in privileged mode
+    #[inline]
+    pub fn port_privilege() -> u32 {
+        // NOTE(unsafe) atomic read with no side effects
+        unsafe { (*Self::PTR).tpr.read() }
+    }
+
+    /// Applies `settings` to the ITM
+    ///
+    /// The ITM is unlocked, then disabled while it is reconfigured, as recommended by the
+    /// architecture. The DWT `TRCENA` bit (`DCB::enable_trace`) must be set for the ITM to be
+    /// accessible.
+    #[inline]
+    pub fn configure(&mut self, settings: ItmSettings) -> Result<(), ItmError> {
+        if let Some(id) = settings.bus_id {
+            if !(0x01..=0x6f).contains(&id) {
+                return Err(ItmError::InvalidBusId);
+            }
+        }
+
+        self.unlock();
+        self.disable();
+        while Self::is_busy() {}
+
+        self.set_local_timestamps(settings.local_timestamps)?;
+        self.set_timestamp_clock(settings.timestamp_clock)?;
+        self.set_global_timestamps(settings.global_timestamps)?;
+        self.set_synchronization(settings.synchronization);
+        self.set_dwt_forwarding(settings.forward_dwt);
+        if let Some(id) = settings.bus_id {
+            self.set_trace_bus_id(id)?;
+        }
+        unsafe {
+            self.ter[0].write(settings.enabled_ports);
+        }
+        self.set_port_privilege(settings.privileged_ports);
+
+        if settings.enable {
+            self.enable();
+        }
+        Ok(())
+    }
+
+    #[inline]
+    fn set_tcr_bit(&mut self, bit: u32, value: bool) {
+        unsafe {
+            self.tcr.modify(|r| if value { r | bit } else { r & !bit });
+        }
+    }
+
+    #[inline]
+    fn ter_position(port: u8) -> (usize, u32) {
+        (usize::from(port / 32), 1 << (port % 32))
+    }
+}
@@ -67,6 +67,8 @@ pub mod cbp;
 pub mod cpuid;
 pub mod dcb;
 pub mod dwt;
+#[cfg(all(not(armv6m), not(armv8m_base)))]
+pub mod etm;
 #[cfg(not(armv6m))]
 pub mod fpb;
 // NOTE(native) is for documentation purposes
@@ -76,7 +78,10 @@ pub mod icb;
 #[cfg(all(not(armv6m), not(armv8m_base)))]
 pub mod itm;
 pub mod mpu;
+#[cfg(not(armv7m))]
+pub mod mtb;
 pub mod nvic;
+pub mod rom_table;
 #[cfg(armv8m)]
 pub mod sau;
 pub mod scb;
@@ -380,6 +385,46 @@ impl ops::Deref for DWT {
     }
 }
 
+/// Embedded Trace Macrocell
+///
+/// Adding a field to [`Peripherals`] is a breaking change, so the ETM is not part of it before
+/// 0.8: use [`ETM::steal`] to get a handle. Not available on Armv6-M and Armv8-M Baseline.
+#[cfg(all(not(armv6m), not(armv8m_base)))]
+pub struct ETM {
+    _marker: PhantomData<*const ()>,
+}
+
+#[cfg(all(not(armv6m), not(armv8m_base)))]
+unsafe impl Send for ETM {}
+
+#[cfg(all(not(armv6m), not(armv8m_base)))]
+impl ETM {
+    /// Pointer to the register block
+    pub const PTR: *const etm::RegisterBlock = 0xE004_1000 as *const _;
+
+    /// Returns a handle to the ETM
+    ///
+    /// # Safety
+    ///
+    /// Only one handle to the ETM must exist at a time.
+    #[inline]
+    pub unsafe fn steal() -> Self {
+        ETM {
+            _marker: PhantomData,
+        }
+    }
+}
+
+#[cfg(all(not(armv6m), not(armv8m_base)))]
+impl ops::Deref for ETM {
+    type Target = self::etm::RegisterBlock;
+
+    #[inline(always)]
+    fn deref(&self) -> &Self::Target {
+        unsafe { &*Self::PTR }
+    }
+}
+
 /// Flash Patch and Breakpoint unit
 pub struct FPB {
     _marker: PhantomData<*const ()>,
@@ -546,6 +591,49 @@ impl ops::Deref for MPU {
     }
 }
 
+/// Micro Trace Buffer
+///
+/// Unlike the other core peripherals, the MTB is located at an implementation defined address,
+/// so it is not part of [`Peripherals`]. Not available on ARMv7-M.
+#[cfg(not(armv7m))]
+pub struct MTB {
+    rb: *const mtb::RegisterBlock,
+}
+
+#[cfg(not(armv7m))]
+unsafe impl Send for MTB {}
+
+#[cfg(not(armv7m))]
+impl MTB {
+    /// Creates a handle to the MTB registers located at `address`
+    ///
+    /// # Safety
+    ///
+    /// An MTB must be implemented at `address`, and only one handle to it must exist at a time.
+    #[inline]
+    pub const unsafe fn new(address: usize) -> Self {
+        MTB {
+            rb: address as *const _,
+        }
+    }
+
+    /// Returns a pointer to the register block
+    #[inline]
+    pub const fn ptr(&self) -> *const mtb::RegisterBlock {
+        self.rb
+    }
+}
+
+#[cfg(not(armv7m))]
+impl ops::Deref for MTB {
+    type Target = self::mtb::RegisterBlock;
+
+    #[inline(always)]
+    fn deref(&self) -> &Self::Target {
+        unsafe { &*self.rb }
+    }
+}
+
 /// Nested Vector Interrupt Controller
 pub struct NVIC {
     _marker: PhantomData<*const ()>,
@@ -0,0 +1,429 @@
+//! Micro Trace Buffer
+//!
+//! *NOTE* Not available on ARMv7-M.
+//!
+//! The MTB records the source and destination address of every non-sequential change of the
+//! program counter into a circular buffer in on-chip SRAM. It is the only instruction trace
+//! available on Cortex-M0+ processors, and is also implemented by some ARMv8-M processors.
+//!
+//! The MTB registers and the SRAM it writes to are located at implementation defined addresses,
+//! see the device reference manual or the CoreSight ROM table.
+//!
+//! # Examples
+//!
+//! Printing the last branches from the `HardFault` handler:
+//!
+//! ``` no_run
+//! use cortex_m::peripheral::MTB;
+//!
+//! // address from the device reference manual
+//! let mut mtb = unsafe { MTB::new(0xF000_0000) };
+//! // NOTE(unsafe) the first 1 KiB of the MTB SRAM is reserved for trace
+//! unsafe { mtb.set_buffer(mtb.sram_base(), 1024).unwrap() };
+//! mtb.start();
+//!
+//! // ... in the HardFault handler
+//! mtb.stop();
+//! for branch in unsafe { mtb.branches() }.rev().take(8) {
+//!     // report `branch.source` -> `branch.destination`
+//! }
+//! ```
+
+use core::slice;
+
+use volatile_register::{RO, RW, WO};
+
+use crate::peripheral::MTB;
+
+const MTB_POSITION_WRAP: u32 = 1 << 2;
+const MTB_POSITION_POINTER_MASK: u32 = !0b111;
+const MTB_MASTER_EN: u32 = 1 << 31;
+const MTB_MASTER_MASK_MASK: u32 = 0b1_1111;
+const MTB_MASTER_TSTARTEN: u32 = 1 << 5;
+const MTB_MASTER_TSTOPEN: u32 = 1 << 6;
+const MTB_FLOW_AUTOSTOP: u32 = 1 << 0;
+const MTB_FLOW_AUTOHALT: u32 = 1 << 1;
+const MTB_LAR_KEY: u32 = 0xC5AC_CE55;
+
+/// Smallest trace buffer, in bytes
+const MIN_BUFFER_SIZE: u32 = 16;
+
+/// Register block
+#[repr(C)]
+pub struct RegisterBlock {
+    /// Position
+    pub position: RW<u32>,
+    /// Master Trace Control
+    pub master: RW<u32>,
+    /// Flow
+    pub flow: RW<u32>,
+    /// SRAM Base Address
+    pub base: RO<u32>,
+    reserved0: [u32; 956],
+    /// Integration Mode Control
+    pub itctrl: RW<u32>,
+    reserved1: [u32; 39],
+    /// Claim Tag Set
+    pub claimset: RW<u32>,
+    /// Claim Tag Clear
+    pub claimclr: RW<u32>,
+    reserved2: [u32; 2],
+    /// Lock Access
+    pub lar: WO<u32>,
+    /// Lock Status
+    pub lsr: RO<u32>,
+    /// Authentication Status
+    pub authstatus: RO<u32>,
+    reserved3: [u32; 3],
+    /// Device Configuration
+    pub devid: RO<u32>,
+    /// Device Type
+    pub devtype: RO<u32>,
+    /// Peripheral Identification 4 to 7, then 0 to 3
+    pub pidr: [RO<u32>; 8],
+    /// Component Identification 0 to 3
+    pub cidr: [RO<u32>; 4],
+}
+
+/// Possible errors of the MTB configuration methods
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum MtbError {
+    /// The buffer size must be a power of two of at least 16 bytes, and the buffer must be
+    /// aligned to its size
+    InvalidBuffer,
+    /// The buffer is not located in the SRAM used by the MTB
+    OutOfSram,
+    /// The watermark offset is not within the buffer
+    WatermarkOutOfBuffer,
+}
+
+/// What the MTB does once the write pointer reaches the watermark (FLOW)
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum WatermarkAction {
+    /// Stops tracing (AUTOSTOP)
+    Stop,
+    /// Halts the processor through the debugger (AUTOHALT)
+    Halt,
+}
+
+/// A branch recorded by the MTB
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Branch {
+    /// Address of the branch instruction, or of the instruction interrupted by an exception
+    pub source: u32,
+    /// Address of the next executed instruction
+    pub destination: u32,
+    /// The branch is an exception entry or an exception return (A bit)
+    pub exception: bool,
+    /// The branch is the first one recorded since tracing started (S bit)
+    pub start: bool,
+}
+
+/// Iterator over the branches recorded in a trace buffer, from the oldest to the most recent
+///
+/// It is double-ended: iterate in reverse to get the most recent branches first.
+#[derive(Clone, Debug)]
+pub struct Branches<'a> {
+    older: slice::ChunksExact<'a, u32>,
+    newer: slice::ChunksExact<'a, u32>,
+}
+
+impl Branches<'_> {
+    fn branch(packet: &[u32]) -> Branch {
+        Branch {
+            source: packet[0] & !1,
+            destination: packet[1] & !1,
+            exception: packet[0] & 1 != 0,
+            start: packet[1] & 1 != 0,
+        }
+    }
+}
+
+impl Iterator for Branches<'_> {
+    type Item = Branch;
+
+    #[inline]
+    fn next(&mut self) -> Option<Branch> {
+        self.older
+            .next()
+            .or_else(|| self.newer.next())
+            .map(Self::branch)
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.older.len() + self.newer.len();
+        (len, Some(len))
+    }
+}
+
+impl DoubleEndedIterator for Branches<'_> {
+    #[inline]
+    fn next_back(&mut self) -> Option<Branch> {
+        self.newer
+            .next_back()
+            .or_else(|| self.older.next_back())
+            .map(Self::branch)
+    }
+}
+
+impl ExactSizeIterator for Branches<'_> {}
+
+/// Decodes the branches recorded in `buffer`, a copy of the trace buffer
+///
+/// `write_offset` is the offset in bytes of the next packet to be written, and `wrapped` tells
+/// whether the write pointer wrapped around since tracing started. See
+/// [`MTB::write_position`].
+///
+/// # Examples
+///
+/// ```
+/// use cortex_m::peripheral::mtb::{self, Branch};
+///
+/// // the MTB wrapped around after writing the second packet
+/// let buffer = [0x100, 0x201, 0x300, 0x400, 0x222, 0x180];
+/// let branches = mtb::decode(&buffer, 8, true);
+///
+/// assert_eq!(
+///     branches.map(|b| (b.source, b.destination)).collect::<Vec<_>>(),
+///     [(0x300, 0x400), (0x222, 0x180), (0x100, 0x200)],
+/// );
+/// ```
+#[inline]
+pub fn decode(buffer: &[u32], write_offset: usize, wrapped: bool) -> Branches<'_> {
+    let split = (write_offset / 4).min(buffer.len()) & !1;
+    let (newer, older) = buffer.split_at(split);
+    Branches {
+        older: if wrapped { older } else { &[] }.chunks_exact(2),
+        newer: newer.chunks_exact(2),
+    }
+}
+
+impl MTB {
+    /// Removes the software lock on the MTB registers, if implemented
+    #[inline]
+    pub fn unlock(&mut self) {
+        unsafe { self.lar.write(MTB_LAR_KEY) }
+    }
+
+    /// Returns the address of the SRAM the MTB writes the trace to (BASE)
+    #[inline]
+    pub fn sram_base(&self) -> u32 {
+        self.base.read()
+    }
+
+    /// Places the trace buffer at `address`, with a size of `size` bytes
+    ///
+    /// This resets the write pointer to the start of the buffer. Tracing must be stopped.
+    ///
+    /// # Safety
+    ///
+    /// The buffer must not be used for anything else while tracing.
+    #[inline]
+    pub unsafe fn set_buffer(&mut self, address: u32, size: u32) -> Result<(), MtbError> {
+        if !size.is_power_of_two() || size < MIN_BUFFER_SIZE || address % size != 0 {
+            return Err(MtbError::InvalidBuffer);
+        }
+        let offset = address
+            .checked_sub(self.sram_base())
+            .ok_or(MtbError::OutOfSram)?;
+
+        // the buffer size is 2^(MASK + 4) bytes
+        let mask = size.trailing_zeros() - 4;
+        if mask > MTB_MASTER_MASK_MASK {
+            return Err(MtbError::InvalidBuffer);
+        }
+
+        unsafe {
+            self.master.modify(|r| (r & !MTB_MASTER_MASK_MASK) | mask);
+            self.position.write(offset);
+        }
+        Ok(())
+    }
+
+    /// Returns the address of the trace buffer
+    #[inline]
+    pub fn buffer_address(&self) -> u32 {
+        let offset = self.position.read() & MTB_POSITION_POINTER_MASK & !(self.buffer_size() - 1);
+        self.sram_base().wrapping_add(offset)
+    }
+
+    /// Returns the size of the trace buffer in bytes
+    #[inline]
+    pub fn buffer_size(&self) -> u32 {
+        MIN_BUFFER_SIZE << (self.master.read() & MTB_MASTER_MASK_MASK)
+    }
+
+    /// Returns the offset in bytes of the next packet in the trace buffer, and whether the write
+    /// pointer wrapped around (POSITION)
+    #[inline]
+    pub fn write_position(&self) -> (u32, bool) {
+        let position = self.position.read();
+        (
+            position & MTB_POSITION_POINTER_MASK & (self.buffer_size() - 1),
+            position & MTB_POSITION_WRAP != 0,
+        )
+    }
+
+    /// Starts tracing (MASTER EN)
+    #[inline]
+    pub fn start(&mut self) {
+        unsafe { self.master.modify(|r| r | MTB_MASTER_EN) }
+    }
+
+    /// Stops tracing
+    #[inline]
+    pub fn stop(&mut self) {
+        unsafe { self.master.modify(|r| r & !MTB_MASTER_EN) }
+    }
+
+    /// Returns `true` if the MTB is tracing
+    #[inline]
+    pub fn is_enabled(&self) -> bool {
+        self.master.read() & MTB_MASTER_EN != 0
+    }
+
+    /// Lets the DWT start and stop tracing through its comparators (TSTARTEN and TSTOPEN)
+    #[inline]
+    pub fn set_dwt_control(&mut self, start: bool, stop: bool) {
+        let mut bits = 0;
+        if start {
+            bits |= MTB_MASTER_TSTARTEN;
+        }
+        if stop {
+            bits |= MTB_MASTER_TSTOPEN;
+        }
+        unsafe {
+            self.master
+                .modify(|r| (r & !(MTB_MASTER_TSTARTEN | MTB_MASTER_TSTOPEN)) | bits)
+        }
+    }
+
+    /// Takes `action` once the write pointer reaches `offset` bytes into the buffer, or never
+    /// with `None` (FLOW)
+    ///
+    /// With [`WatermarkAction::Stop`] the oldest packets are preserved instead of overwritten.
+    ///
+    /// Returns `Err(MtbError::WatermarkOutOfBuffer)` if `offset` is not smaller than the buffer
+    /// size, as the write pointer would never reach the watermark.
+    #[inline]
+    pub fn set_watermark(
+        &mut self,
+        watermark: Option<(u32, WatermarkAction)>,
+    ) -> Result<(), MtbError> {
+        let flow = match watermark {
+            None => 0,
+            Some((offset, action)) => {
+                let size = self.buffer_size();
+                if offset >= size {
+                    return Err(MtbError::WatermarkOutOfBuffer);
+                }
+                let base = self.position.read() & MTB_POSITION_POINTER_MASK & !(size - 1);
+                let flag = match action {
+                    WatermarkAction::Stop => MTB_FLOW_AUTOSTOP,
+                    WatermarkAction::Halt => MTB_FLOW_AUTOHALT,
+                };
+                (base.wrapping_add(offset) & MTB_POSITION_POINTER_MASK) | flag
+            }
+        };
+        unsafe { self.flow.write(flow) }
+        Ok(())
+    }
+
+    /// Returns the branches recorded in the trace buffer, from the oldest to the most recent
+    ///
+    /// Tracing should be stopped, or the branches taken while iterating overwrite the oldest
+    /// ones.
+    ///
+    /// # Safety
+    ///
+    /// The trace buffer must have been placed with [`MTB::set_buffer`].
+    #[inline]
+    pub unsafe fn branches(&self) -> Branches<'static> {
+        let (write_offset, wrapped) = self.write_position();
+        let buffer = unsafe {
+            slice::from_raw_parts(
+                self.buffer_address() as *const u32,
+                self.buffer_size() as usize / 4,
+            )
+        };
+        decode(buffer, write_offset as usize, wrapped)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn branch(source: u32, destination: u32) -> Branch {
+        Branch {
+            source,
+            destination,
+            exception: false,
+            start: false,
+        }
+    }
+
+    #[test]
+    fn decode_wrapped() {
+        // the packets at offsets 0 and 8 were written after wrapping around, the write pointer
+        // is at offset 16
+        let buffer = [0x100, 0x200, 0x300, 0x400, 0x500, 0x600, 0x700, 0x800];
+        let branches = decode(&buffer, 16, true);
+
+        assert_eq!(branches.len(), 4);
+        assert!(branches.clone().eq([
+            branch(0x500, 0x600),
+            branch(0x700, 0x800),
+            branch(0x100, 0x200),
+            branch(0x300, 0x400),
+        ]));
+        assert!(branches.rev().eq([
+            branch(0x300, 0x400),
+            branch(0x100, 0x200),
+            branch(0x700, 0x800),
+            branch(0x500, 0x600),
+        ]));
+
+        // before wrapping around, only the packets before the write pointer are valid
+        assert!(decode(&buffer, 16, false).eq([branch(0x100, 0x200), branch(0x300, 0x400)]));
+    }
+
+    #[test]
+    fn decode_flags() {
+        // an exception entry (A bit), then the first branch after tracing restarted (S bit)
+        let buffer = [0x101, 0x200, 0x300, 0x401];
+        let mut branches = decode(&buffer, 16, false);
+
+        assert_eq!(
+            branches.next(),
+            Some(Branch {
+                source: 0x100,
+                destination: 0x200,
+                exception: true,
+                start: false,
+            })
+        );
+        assert_eq!(
+            branches.next(),
+            Some(Branch {
+                source: 0x300,
+                destination: 0x400,
+                exception: false,
+                start: true,
+            })
+        );
+        assert_eq!(branches.next(), None);
+    }
+
+    #[test]
+    fn decode_empty() {
+        assert_eq!(decode(&[], 0, false).len(), 0);
+        assert_eq!(decode(&[], 8, true).len(), 0);
+
+        // nothing was written since tracing started
+        let buffer = [0x100, 0x200, 0x300, 0x400];
+        assert_eq!(decode(&buffer, 0, false).len(), 0);
+        assert_eq!(decode(&buffer, 0, false).next_back(), None);
+    }
+}
@@ -1,11 +1,22 @@
 //! Nested Vector Interrupt Controller
 
+use core::marker::PhantomData;
+#[cfg(armv8m)]
+use core::sync::atomic::{AtomicBool, Ordering};
+
 use volatile_register::RW;
 #[cfg(not(armv6m))]
 use volatile_register::{RO, WO};
 
 use crate::interrupt::InterruptNumber;
+#[cfg(not(armv6m))]
+use crate::peripheral::ICB;
 use crate::peripheral::NVIC;
+use crate::priority::Priority;
+
+/// Set by `NVIC::lock_routing`
+#[cfg(armv8m)]
+static ROUTING_LOCKED: AtomicBool = AtomicBool::new(false);
 
 /// Register block
 #[repr(C)]
@@ -245,6 +256,35 @@ impl NVIC {
         }
     }
 
+    /// Returns the logical priority level of `interrupt`, on a device implementing `BITS`
+    /// priority bits
+    ///
+    /// See [`Priority`] for the mapping between logical levels and hardware priorities.
+    #[inline]
+    pub fn priority_level<const BITS: u8, I>(interrupt: I) -> Priority<BITS>
+    where
+        I: InterruptNumber,
+    {
+        Priority::from_hw(Self::get_priority(interrupt))
+    }
+
+    /// Sets the logical priority level of `interrupt` to `prio`
+    ///
+    /// # Unsafety
+    ///
+    /// Changing priority levels can break priority-based critical sections (see
+    /// [`register::basepri`](crate::register::basepri)) and compromise memory safety.
+    #[inline]
+    pub unsafe fn set_priority_level<const BITS: u8, I>(
+        &mut self,
+        interrupt: I,
+        prio: Priority<BITS>,
+    ) where
+        I: InterruptNumber,
+    {
+        unsafe { self.set_priority(interrupt, prio.to_hw()) }
+    }
+
     /// Clears `interrupt`'s pending state
     #[inline]
     pub fn unpend<I>(interrupt: I)
@@ -311,6 +351,282 @@ impl NVIC {
         unsafe { ((*Self::PTR).itns[group_idx].read() & bit_mask) == bit_mask }
     }
 
+    /// Routes every implemented interrupt as described by `table`, and checks the result (ARMv8-M
+    /// only)
+    ///
+    /// Each 32-bit ITNS word is written with a single store, so an interrupt never observes a
+    /// partially applied word. The table is then read back with [`NVIC::verify_routing`].
+    ///
+    /// Returns [`RoutingError::Locked`] without writing anything once [`NVIC::lock_routing`] has
+    /// been called, and [`RoutingError::NotImplemented`] if `table` routes an interrupt that is
+    /// not implemented to Non-Secure.
+    ///
+    /// # Safety
+    ///
+    /// Must be called from the Secure world. Routing an interrupt to Non-Secure while Secure
+    /// handlers depend on it can violate security invariants.
+    #[cfg(armv8m)]
+    #[inline]
+    pub unsafe fn configure_routing(&mut self, table: &RoutingTable) -> Result<(), RoutingError> {
+        if ROUTING_LOCKED.load(Ordering::Acquire) {
+            return Err(RoutingError::Locked);
+        }
+
+        let words = usize::from(Self::implemented_lines()).div_ceil(32);
+        if let Some(word) = table.words[words..].iter().position(|&word| word != 0) {
+            let word = words + word;
+            let bit = table.words[word].trailing_zeros() as u16;
+            return Err(RoutingError::NotImplemented(word as u16 * 32 + bit));
+        }
+
+        for (itns, &word) in self.itns.iter().zip(&table.words[..words]) {
+            unsafe { itns.write(word) }
+        }
+        crate::asm::dsb();
+
+        Self::verify_routing(table)
+    }
+
+    /// Returns the current routing of every interrupt (ARMv8-M only)
+    #[cfg(armv8m)]
+    #[inline]
+    pub fn routing() -> RoutingTable {
+        // NOTE(unsafe) atomic reads with no side effects
+        let nvic = unsafe { &*Self::PTR };
+        let words = usize::from(Self::implemented_lines()).div_ceil(32);
+
+        let mut table = RoutingTable::new();
+        for (word, itns) in table.words.iter_mut().zip(&nvic.itns[..words]) {
+            *word = itns.read();
+        }
+        table
+    }
+
+    /// Checks that the interrupts are routed as described by `table` (ARMv8-M only)
+    ///
+    /// This is meant for secure boot attestation: the first difference is reported as
+    /// [`RoutingError::Mismatch`].
+    #[cfg(armv8m)]
+    #[inline]
+    pub fn verify_routing(table: &RoutingTable) -> Result<(), RoutingError> {
+        let actual = Self::routing();
+        match table
+            .words
+            .iter()
+            .zip(&actual.words)
+            .position(|(expected, actual)| expected != actual)
+        {
+            None => Ok(()),
+            Some(word) => Err(RoutingError::Mismatch {
+                word,
+                expected: table.words[word],
+                actual: actual.words[word],
+            }),
+        }
+    }
+
+    /// Rejects further calls to [`NVIC::configure_routing`] (ARMv8-M only)
+    ///
+    /// The lock is a convention of this crate, not a hardware lock: [`NVIC::route_to_secure`],
+    /// [`NVIC::route_to_nonsecure`] and direct writes to ITNS still change the routing. It can't
+    /// be released until the next reset.
+    #[cfg(armv8m)]
+    #[inline]
+    pub fn lock_routing(&mut self) {
+        ROUTING_LOCKED.store(true, Ordering::Release);
+    }
+
+    /// Returns `true` if [`NVIC::lock_routing`] has been called (ARMv8-M only)
+    #[cfg(armv8m)]
+    #[inline]
+    pub fn is_routing_locked() -> bool {
+        ROUTING_LOCKED.load(Ordering::Acquire)
+    }
+
+    /// Captures the enable, pending, priority and, on ARMv8-M, target security state of every
+    /// implemented interrupt
+    ///
+    /// See [`NVIC::restore`] to apply the state again, e.g. after a deep sleep cycle which loses
+    /// the NVIC state.
+    #[inline]
+    pub fn snapshot() -> NvicSnapshot {
+        // NOTE(unsafe) atomic reads with no side effects
+        let nvic = unsafe { &*Self::PTR };
+        let lines = Self::implemented_lines();
+        let words = usize::from(lines).div_ceil(32);
+
+        let mut snapshot = NvicSnapshot {
+            lines,
+            enabled: [0; 16],
+            pending: [0; 16],
+            #[cfg(armv8m)]
+            nonsecure: [0; 16],
+            priorities: [0; 496],
+        };
+        for word in 0..words {
+            snapshot.enabled[word] = nvic.iser[word].read();
+            snapshot.pending[word] = nvic.ispr[word].read();
+            #[cfg(armv8m)]
+            {
+                snapshot.nonsecure[word] = nvic.itns[word].read();
+            }
+        }
+        for (line, priority) in snapshot.priorities[..usize::from(lines)]
+            .iter_mut()
+            .enumerate()
+        {
+            #[cfg(not(armv6m))]
+            {
+                *priority = nvic.ipr[line].read();
+            }
+            #[cfg(armv6m)]
+            {
+                *priority = (nvic.ipr[line / 4].read() >> ((line % 4) * 8)) as u8;
+            }
+        }
+        snapshot
+    }
+
+    /// Applies the interrupt state captured by [`NVIC::snapshot`]
+    ///
+    /// All implemented interrupts are first disabled and unpended. The priorities and target
+    /// security states are then restored, before the pending and enable states. Interrupts not
+    /// implemented when the snapshot was taken are left disabled.
+    ///
+    /// # Unsafety
+    ///
+    /// Enabling interrupts or changing their priority can break mask-based and priority-based
+    /// critical sections.
+    #[inline]
+    pub unsafe fn restore(&mut self, snapshot: &NvicSnapshot) {
+        self.disable_all_and_clear_pending();
+
+        let lines = snapshot.lines.min(Self::implemented_lines());
+        let words = usize::from(lines).div_ceil(32);
+
+        unsafe {
+            #[cfg(not(armv6m))]
+            for (line, &priority) in snapshot.priorities[..usize::from(lines)].iter().enumerate() {
+                self.ipr[line].write(priority);
+            }
+            #[cfg(armv6m)]
+            for (word, priorities) in snapshot.priorities[..usize::from(lines)]
+                .chunks(4)
+                .enumerate()
+            {
+                let mut value = [0; 4];
+                value[..priorities.len()].copy_from_slice(priorities);
+                self.ipr[word].write(u32::from_le_bytes(value));
+            }
+
+            for word in 0..words {
+                #[cfg(armv8m)]
+                self.itns[word].write(snapshot.nonsecure[word]);
+                self.ispr[word].write(snapshot.pending[word]);
+                self.iser[word].write(snapshot.enabled[word]);
+            }
+        }
+    }
+
+    /// Disables and unpends every implemented interrupt
+    ///
+    /// Once this returns, no interrupt is taken until one is enabled again, e.g. to hand over a
+    /// clean NVIC state to another image.
+    #[inline]
+    pub fn disable_all_and_clear_pending(&mut self) {
+        let words = usize::from(Self::implemented_lines()).div_ceil(32);
+        for word in 0..words {
+            unsafe {
+                self.icer[word].write(!0);
+                self.icpr[word].write(!0);
+            }
+        }
+
+        // ensure that no interrupt is taken after the interrupts are disabled
+        crate::asm::dsb();
+        crate::asm::isb();
+    }
+
+    /// Returns the number of implemented interrupt lines
+    ///
+    /// The ICTR register gives it as a multiple of 32, so the device may implement fewer
+    /// interrupts. On ARMv6-M, which has no ICTR, this is always 32.
+    #[inline]
+    pub fn implemented_lines() -> u16 {
+        #[cfg(not(armv6m))]
+        {
+            // NOTE(unsafe) atomic read with no side effects
+            let ictr = unsafe { (*ICB::PTR).ictr.read() };
+            (32 * ((ictr & 0xf) as u16 + 1)).min(496)
+        }
+
+        #[cfg(armv6m)]
+        {
+            32
+        }
+    }
+
+    /// Returns an iterator over the pending interrupts, in ascending order (ISPR)
+    ///
+    /// Interrupt numbers that `I` can't represent are yielded as `Err(number)`.
+    ///
+    /// # Examples
+    ///
+    /// ``` no_run
+    /// # use cortex_m::peripheral::NVIC;
+    /// # #[derive(Clone, Copy, Debug)]
+    /// # struct Interrupt(u16);
+    /// # unsafe impl cortex_m::interrupt::InterruptNumber for Interrupt {
+    /// #     fn number(self) -> u16 { self.0 }
+    /// # }
+    /// # impl TryFrom<u16> for Interrupt {
+    /// #     type Error = ();
+    /// #     fn try_from(nr: u16) -> Result<Self, ()> { Ok(Interrupt(nr)) }
+    /// # }
+    /// // e.g. from the DefaultHandler
+    /// for interrupt in NVIC::pending::<Interrupt>() {
+    ///     match interrupt {
+    ///         Ok(interrupt) => { /* report `interrupt` */ }
+    ///         Err(nr) => { /* report the unknown interrupt number `nr` */ }
+    ///     }
+    /// }
+    /// ```
+    #[inline]
+    pub fn pending<I>() -> Interrupts<I>
+    where
+        I: InterruptNumber + TryFrom<u16>,
+    {
+        // NOTE(unsafe) atomic reads with no side effects
+        Interrupts::new(unsafe { &(*Self::PTR).ispr })
+    }
+
+    /// Returns an iterator over the enabled interrupts, in ascending order (ISER)
+    ///
+    /// Interrupt numbers that `I` can't represent are yielded as `Err(number)`.
+    #[inline]
+    pub fn enabled<I>() -> Interrupts<I>
+    where
+        I: InterruptNumber + TryFrom<u16>,
+    {
+        // NOTE(unsafe) atomic reads with no side effects
+        Interrupts::new(unsafe { &(*Self::PTR).iser })
+    }
+
+    /// Returns an iterator over the active interrupts, in ascending order (IABR)
+    ///
+    /// *NOTE* Not available on ARMv6-M.
+    ///
+    /// Interrupt numbers that `I` can't represent are yielded as `Err(number)`.
+    #[cfg(not(armv6m))]
+    #[inline]
+    pub fn active<I>() -> Interrupts<I>
+    where
+        I: InterruptNumber + TryFrom<u16>,
+    {
+        // NOTE(unsafe) atomic reads with no side effects
+        Interrupts::new(unsafe { &(*Self::PTR).iabr })
+    }
+
     #[cfg(armv6m)]
     #[inline]
     fn ipr_index<I>(interrupt: I) -> usize
@@ -329,3 +645,269 @@ impl NVIC {
         (usize::from(interrupt.number()) % 4) * 8
     }
 }
+
+/// Iterator over the interrupts whose bit is set in a NVIC register, see [`NVIC::pending`]
+///
+/// Each 32-bit word of the register is read once, when the iterator reaches it.
+pub struct Interrupts<I> {
+    /// First word of the register
+    register: *const u32,
+    /// Number of implemented words
+    words: usize,
+    /// Index of the next word to read
+    word: usize,
+    /// Bits of the current word that were not yielded yet
+    bits: u32,
+    _marker: PhantomData<fn() -> I>,
+}
+
+impl<I> Interrupts<I> {
+    fn new<R>(register: &[R; 16]) -> Self {
+        let lines = usize::from(NVIC::implemented_lines());
+        Interrupts {
+            register: register.as_ptr().cast(),
+            words: lines.div_ceil(32),
+            word: 0,
+            bits: 0,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<I> Iterator for Interrupts<I>
+where
+    I: InterruptNumber + TryFrom<u16>,
+{
+    type Item = Result<I, u16>;
+
+    #[inline]
+    fn next(&mut self) -> Option<Result<I, u16>> {
+        while self.bits == 0 {
+            if self.word == self.words {
+                return None;
+            }
+            // NOTE(unsafe) atomic read with no side effects
+            self.bits = unsafe { self.register.add(self.word).read_volatile() };
+            self.word += 1;
+        }
+
+        let bit = self.bits.trailing_zeros();
+        self.bits &= self.bits - 1;
+        let nr = (self.word as u16 - 1) * 32 + bit as u16;
+        Some(I::try_from(nr).map_err(|_| nr))
+    }
+}
+
+impl<I> core::fmt::Debug for Interrupts<I> {
+    #[inline]
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("Interrupts")
+            .field("word", &self.word)
+            .field("bits", &self.bits)
+            .finish()
+    }
+}
+
+/// Target security state of an interrupt (ARMv8-M only)
+#[cfg(armv8m)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Security {
+    /// The interrupt is taken in the Secure world (the default after reset)
+    Secure,
+    /// The interrupt is taken in the Non-Secure world
+    NonSecure,
+}
+
+/// Target security state of every interrupt, as a bitmap of the ITNS words (ARMv8-M only)
+///
+/// # Examples
+///
+/// ``` no_run
+/// use cortex_m::peripheral::nvic::{RoutingTable, Security};
+/// # #[derive(Clone, Copy)]
+/// # struct Interrupt(u16);
+/// # unsafe impl cortex_m::interrupt::InterruptNumber for Interrupt {
+/// #     fn number(self) -> u16 { self.0 }
+/// # }
+/// # const UART0: Interrupt = Interrupt(5);
+/// # const TIMER1: Interrupt = Interrupt(33);
+///
+/// let mut peripherals = cortex_m::Peripherals::take().unwrap();
+/// let table = RoutingTable::from_routes(&[
+///     (UART0, Security::NonSecure),
+///     (TIMER1, Security::NonSecure),
+/// ]);
+/// // NOTE(unsafe) running in the Secure world, before starting the Non-Secure application
+/// unsafe { peripherals.NVIC.configure_routing(&table) }.unwrap();
+/// peripherals.NVIC.lock_routing();
+/// ```
+#[cfg(armv8m)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct RoutingTable {
+    words: [u32; 16],
+}
+
+#[cfg(armv8m)]
+impl RoutingTable {
+    /// Creates a table routing every interrupt to the Secure world
+    #[inline]
+    pub const fn new() -> Self {
+        RoutingTable { words: [0; 16] }
+    }
+
+    /// Creates a table from the value of each ITNS word: a set bit routes the interrupt to the
+    /// Non-Secure world
+    #[inline]
+    pub const fn from_words(words: [u32; 16]) -> Self {
+        RoutingTable { words }
+    }
+
+    /// Creates a table routing the listed interrupts, and every other interrupt to the Secure
+    /// world
+    ///
+    /// When an interrupt is listed several times, the last entry wins.
+    #[inline]
+    pub fn from_routes<I>(routes: &[(I, Security)]) -> Self
+    where
+        I: InterruptNumber,
+    {
+        let mut table = Self::new();
+        for &(interrupt, security) in routes {
+            table.set(interrupt, security);
+        }
+        table
+    }
+
+    /// Routes `interrupt` to `security`
+    #[inline]
+    pub fn set<I>(&mut self, interrupt: I, security: Security)
+    where
+        I: InterruptNumber,
+    {
+        let nr = interrupt.number();
+        let word = &mut self.words[usize::from(nr / 32)];
+        match security {
+            Security::Secure => *word &= !(1 << (nr % 32)),
+            Security::NonSecure => *word |= 1 << (nr % 32),
+        }
+    }
+
+    /// Returns the routing of `interrupt`
+    #[inline]
+    pub fn get<I>(&self, interrupt: I) -> Security
+    where
+        I: InterruptNumber,
+    {
+        let nr = interrupt.number();
+        if self.words[usize::from(nr / 32)] & (1 << (nr % 32)) != 0 {
+            Security::NonSecure
+        } else {
+            Security::Secure
+        }
+    }
+
+    /// Returns the value of each ITNS word
+    #[inline]
+    pub const fn words(&self) -> &[u32; 16] {
+        &self.words
+    }
+}
+
+#[cfg(armv8m)]
+impl Default for RoutingTable {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Possible errors of the interrupt routing methods (ARMv8-M only)
+#[cfg(armv8m)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum RoutingError {
+    /// [`NVIC::lock_routing`] has been called
+    Locked,
+    /// The interrupt with this number is routed to Non-Secure, but is not implemented
+    NotImplemented(u16),
+    /// An ITNS word does not have the expected value
+    Mismatch {
+        /// Index of the word
+        word: usize,
+        /// Expected value
+        expected: u32,
+        /// Value read back
+        actual: u32,
+    },
+}
+
+/// State of the implemented interrupts, captured by [`NVIC::snapshot`]
+///
+/// The snapshot has room for the 496 interrupts supported by the architecture, and records how
+/// many are implemented.
+#[derive(Clone)]
+pub struct NvicSnapshot {
+    lines: u16,
+    enabled: [u32; 16],
+    pending: [u32; 16],
+    #[cfg(armv8m)]
+    nonsecure: [u32; 16],
+    priorities: [u8; 496],
+}
+
+impl NvicSnapshot {
+    /// Returns the number of interrupt lines captured
+    #[inline]
+    pub fn lines(&self) -> u16 {
+        self.lines
+    }
+
+    /// Returns `true` if `interrupt` was enabled
+    #[inline]
+    pub fn is_enabled<I>(&self, interrupt: I) -> bool
+    where
+        I: InterruptNumber,
+    {
+        Self::bit(&self.enabled, interrupt)
+    }
+
+    /// Returns `true` if `interrupt` was pending
+    #[inline]
+    pub fn is_pending<I>(&self, interrupt: I) -> bool
+    where
+        I: InterruptNumber,
+    {
+        Self::bit(&self.pending, interrupt)
+    }
+
+    /// Returns `true` if `interrupt` was routed to the Non-Secure world (ARMv8-M only)
+    #[cfg(armv8m)]
+    #[inline]
+    pub fn is_routed_to_nonsecure<I>(&self, interrupt: I) -> bool
+    where
+        I: InterruptNumber,
+    {
+        Self::bit(&self.nonsecure, interrupt)
+    }
+
+    /// Returns the hardware priority of `interrupt`
+    #[inline]
+    pub fn priority<I>(&self, interrupt: I) -> u8
+    where
+        I: InterruptNumber,
+    {
+        self.priorities
+            .get(usize::from(interrupt.number()))
+            .copied()
+            .unwrap_or(0)
+    }
+
+    fn bit<I>(words: &[u32; 16], interrupt: I) -> bool
+    where
+        I: InterruptNumber,
+    {
+        let nr = interrupt.number();
+        words
+            .get(usize::from(nr / 32))
+            .is_some_and(|word| word & (1 << (nr % 32)) != 0)
+    }
+}
@@ -0,0 +1,277 @@
+//! CoreSight ROM table discovery
+//!
+//! The core peripherals are located at fixed addresses, but most of the debug and trace
+//! components are optional, and vendors add their own. The ROM table lists the components that
+//! are implemented, and [`components`] walks it to identify them from their peripheral and
+//! component identification registers (PIDR and CIDR).
+//!
+//! The trace components (DWT, ITM, ETM, TPIU) can only be identified once `DCB::enable_trace()`
+//! has been called.
+//!
+//! # Examples
+//!
+//! ``` no_run
+//! use cortex_m::peripheral::MTB;
+//! use cortex_m::peripheral::rom_table::{self, ComponentKind};
+//!
+//! if let Some(mtb) = rom_table::components().find(|c| c.kind == ComponentKind::Mtb) {
+//!     // NOTE(unsafe) the ROM table reports an MTB at this address
+//!     let mtb = unsafe { MTB::new(mtb.base as usize) };
+//! }
+//! ```
+
+use core::ptr;
+
+/// Address of the ROM table in the Private Peripheral Bus
+pub const PPB_ROM_TABLE: u32 = 0xE00F_F000;
+
+/// Maximum depth of nested ROM tables
+const MAX_DEPTH: usize = 4;
+/// Maximum number of entries of a ROM table (the 4 KiB block before the ID registers)
+const MAX_ENTRIES: u32 = 960;
+
+const ENTRY_PRESENT: u32 = 1 << 0;
+const ENTRY_FORMAT_32BIT: u32 = 1 << 1;
+const ENTRY_OFFSET_MASK: u32 = 0xffff_f000;
+
+const DEVTYPE: u32 = 0xfcc;
+const PIDR4: u32 = 0xfd0;
+const PIDR0: u32 = 0xfe0;
+const CIDR0: u32 = 0xff0;
+
+/// JEP106 identity code of ARM
+const JEP106_ARM: (u8, u8) = (0x4, 0x3b);
+
+/// Component class, from CIDR1
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ComponentClass {
+    /// ROM table, listing other components
+    RomTable,
+    /// CoreSight component
+    CoreSight,
+    /// Generic IP component, e.g. the SCS on ARMv7-M
+    GenericIp,
+    /// PrimeCell or system component
+    PrimeCell,
+    /// An unknown class, reported as the raw CLASS field
+    Other(u8),
+}
+
+impl ComponentClass {
+    fn from_bits(class: u8) -> Self {
+        match class {
+            0x1 => ComponentClass::RomTable,
+            0x9 => ComponentClass::CoreSight,
+            0xe => ComponentClass::GenericIp,
+            0xf => ComponentClass::PrimeCell,
+            _ => ComponentClass::Other(class),
+        }
+    }
+}
+
+/// Kind of a component, as far as it can be identified
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ComponentKind {
+    /// ROM table
+    RomTable,
+    /// System Control Space: SCB, NVIC, SysTick, MPU, DCB...
+    Scs,
+    /// Data Watchpoint and Trace unit
+    Dwt,
+    /// Flash Patch and Breakpoint unit
+    Fpb,
+    /// Instrumentation Trace Macrocell
+    Itm,
+    /// Trace Port Interface Unit
+    Tpiu,
+    /// Embedded Trace Macrocell
+    Etm,
+    /// Micro Trace Buffer
+    Mtb,
+    /// Cross Trigger Interface
+    Cti,
+    /// Another component, e.g. vendor specific; see its designer and part number
+    Other,
+}
+
+/// A component listed in a ROM table
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Component {
+    /// Address of the first register of the component
+    pub base: u32,
+    /// Kind of component
+    pub kind: ComponentKind,
+    /// Component class
+    pub class: ComponentClass,
+    /// JEP106 continuation code of the designer (PIDR4)
+    pub jep106_continuation: u8,
+    /// JEP106 identity code of the designer (PIDR1 and PIDR2)
+    pub jep106_id: u8,
+    /// Part number (PIDR0 and PIDR1)
+    pub part: u16,
+    /// Revision (PIDR2)
+    pub revision: u8,
+    /// Device type (DEVTYPE), only meaningful for CoreSight components
+    pub devtype: u8,
+}
+
+impl Component {
+    /// Returns `true` if the component was designed by ARM
+    #[inline]
+    pub fn is_arm(&self) -> bool {
+        (self.jep106_continuation, self.jep106_id) == JEP106_ARM
+    }
+
+    /// Reads the identification registers of the component whose last 4 KiB block is at
+    /// `address`
+    ///
+    /// Returns `None` if the component identification preamble is invalid.
+    unsafe fn read(address: u32) -> Option<Self> {
+        let id = |offset: u32, n: u32| unsafe {
+            ptr::read_volatile((address + offset + 4 * n) as *const u32) as u8
+        };
+
+        let cidr = [id(CIDR0, 0), id(CIDR0, 1), id(CIDR0, 2), id(CIDR0, 3)];
+        if cidr[0] != 0x0d || cidr[1] & 0x0f != 0 || cidr[2] != 0x05 || cidr[3] != 0xb1 {
+            return None;
+        }
+        let pidr = [id(PIDR0, 0), id(PIDR0, 1), id(PIDR0, 2), id(PIDR4, 0)];
+
+        let class = ComponentClass::from_bits(cidr[1] >> 4);
+        // the component spans 2^SIZE blocks of 4 KiB, the last one holding the ID registers
+        let blocks = 1 << (pidr[3] >> 4);
+        let devtype = if class == ComponentClass::CoreSight {
+            id(DEVTYPE, 0)
+        } else {
+            0
+        };
+
+        let mut component = Component {
+            base: address.wrapping_sub((blocks - 1) * 0x1000),
+            kind: ComponentKind::Other,
+            class,
+            jep106_continuation: pidr[3] & 0x0f,
+            jep106_id: (pidr[1] >> 4) | ((pidr[2] & 0b111) << 4),
+            part: u16::from(pidr[0]) | (u16::from(pidr[1] & 0x0f) << 8),
+            revision: pidr[2] >> 4,
+            devtype,
+        };
+        component.kind = component.identify();
+        Some(component)
+    }
+
+    fn identify(&self) -> ComponentKind {
+        if self.class == ComponentClass::RomTable {
+            return ComponentKind::RomTable;
+        }
+
+        // the architecture fixes the location of these components
+        match self.base {
+            0xE000_E000 => return ComponentKind::Scs,
+            0xE000_1000 => return ComponentKind::Dwt,
+            0xE000_2000 => return ComponentKind::Fpb,
+            0xE000_0000 => return ComponentKind::Itm,
+            _ => {}
+        }
+
+        if self.class == ComponentClass::CoreSight {
+            match self.devtype {
+                // trace sink, port
+                0x11 => return ComponentKind::Tpiu,
+                // trace source, processor
+                0x13 => return ComponentKind::Etm,
+                // debug control, trigger matrix
+                0x14 => return ComponentKind::Cti,
+                // trace sink, buffer
+                0x31 => return ComponentKind::Mtb,
+                _ => {}
+            }
+        }
+
+        if self.is_arm() {
+            match self.part {
+                0x923 | 0x9a1 | 0x9a9 => return ComponentKind::Tpiu,
+                0x924 | 0x925 | 0x975 => return ComponentKind::Etm,
+                0x932 => return ComponentKind::Mtb,
+                _ => {}
+            }
+        }
+
+        ComponentKind::Other
+    }
+}
+
+/// Iterator over the components listed in a ROM table, see [`components`]
+#[derive(Clone, Debug)]
+pub struct Components {
+    /// ROM tables being walked, with the index of their next entry
+    tables: [(u32, u32); MAX_DEPTH],
+    depth: usize,
+}
+
+impl Iterator for Components {
+    type Item = Component;
+
+    #[inline]
+    fn next(&mut self) -> Option<Component> {
+        while self.depth != 0 {
+            let (table, index) = &mut self.tables[self.depth - 1];
+            let table = *table;
+            if *index == MAX_ENTRIES {
+                self.depth -= 1;
+                continue;
+            }
+
+            // NOTE(unsafe) the entries of a ROM table are readable without side effects
+            let entry = unsafe { ptr::read_volatile((table + 4 * *index) as *const u32) };
+            *index += 1;
+
+            if entry == 0 {
+                // end of the table
+                self.depth -= 1;
+                continue;
+            }
+            if entry & ENTRY_FORMAT_32BIT == 0 || entry & ENTRY_PRESENT == 0 {
+                continue;
+            }
+
+            let address = table.wrapping_add(entry & ENTRY_OFFSET_MASK);
+            // NOTE(unsafe) present components are readable
+            let Some(component) = (unsafe { Component::read(address) }) else {
+                continue;
+            };
+
+            if component.kind == ComponentKind::RomTable
+                && self.depth < MAX_DEPTH
+                && !self.tables[..self.depth].iter().any(|&(t, _)| t == address)
+            {
+                self.tables[self.depth] = (address, 0);
+                self.depth += 1;
+            }
+            return Some(component);
+        }
+        None
+    }
+}
+
+/// Walks the ROM table at `address`, including nested ROM tables
+///
+/// # Safety
+///
+/// A CoreSight ROM table must be located at `address`.
+#[inline]
+pub unsafe fn walk(address: u32) -> Components {
+    let mut tables = [(0, 0); MAX_DEPTH];
+    tables[0] = (address, 0);
+    Components { tables, depth: 1 }
+}
+
+/// Walks the ROM table of the Private Peripheral Bus, including nested ROM tables
+///
+/// On processors where the PPB ROM table is listed by a higher level ROM table, e.g. Cortex-M7,
+/// components outside of the processor are only found by walking that table with [`walk`].
+#[inline]
+pub fn components() -> Components {
+    // NOTE(unsafe) the architecture requires a ROM table at this address
+    unsafe { walk(PPB_ROM_TABLE) }
+}
@@ -15,6 +15,7 @@ use super::CPUID;
 use super::SCB;
 #[cfg(not(armv6m))]
 use super::cpuid::CsselrCacheType;
+use crate::priority::Priority;
 #[cfg(feature = "serde")]
 use serde::{Deserialize, Serialize};
 
@@ -991,6 +992,96 @@ impl SCB {
     }
 }
 
+#[cfg(not(armv6m))]
+const SCB_DFSR_HALTED: u32 = 1 << 0;
+#[cfg(not(armv6m))]
+const SCB_DFSR_BKPT: u32 = 1 << 1;
+#[cfg(not(armv6m))]
+const SCB_DFSR_DWTTRAP: u32 = 1 << 2;
+#[cfg(not(armv6m))]
+const SCB_DFSR_VCATCH: u32 = 1 << 3;
+#[cfg(not(armv6m))]
+const SCB_DFSR_EXTERNAL: u32 = 1 << 4;
+
+/// Contents of the Debug Fault Status Register
+///
+/// Each flag records a kind of debug event that occurred since it was last cleared.
+#[cfg(not(armv6m))]
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct DebugFaultStatus {
+    /// Halt request or single-step (HALTED)
+    pub halted: bool,
+    /// `BKPT` instruction or FPB breakpoint (BKPT)
+    pub breakpoint: bool,
+    /// DWT match (DWTTRAP)
+    pub watchpoint: bool,
+    /// Vector catch (VCATCH)
+    pub vector_catch: bool,
+    /// External debug request signal (EXTERNAL)
+    pub external: bool,
+}
+
+#[cfg(not(armv6m))]
+impl DebugFaultStatus {
+    #[inline]
+    fn from_bits(bits: u32) -> Self {
+        DebugFaultStatus {
+            halted: bits & SCB_DFSR_HALTED != 0,
+            breakpoint: bits & SCB_DFSR_BKPT != 0,
+            watchpoint: bits & SCB_DFSR_DWTTRAP != 0,
+            vector_catch: bits & SCB_DFSR_VCATCH != 0,
+            external: bits & SCB_DFSR_EXTERNAL != 0,
+        }
+    }
+
+    #[inline]
+    fn bits(self) -> u32 {
+        let mut bits = 0;
+        if self.halted {
+            bits |= SCB_DFSR_HALTED;
+        }
+        if self.breakpoint {
+            bits |= SCB_DFSR_BKPT;
+        }
+        if self.watchpoint {
+            bits |= SCB_DFSR_DWTTRAP;
+        }
+        if self.vector_catch {
+            bits |= SCB_DFSR_VCATCH;
+        }
+        if self.external {
+            bits |= SCB_DFSR_EXTERNAL;
+        }
+        bits
+    }
+
+    /// Returns `true` if no flag is set
+    #[inline]
+    pub fn is_empty(self) -> bool {
+        self.bits() == 0
+    }
+}
+
+#[cfg(not(armv6m))]
+impl SCB {
+    /// Reads the Debug Fault Status Register
+    #[inline]
+    pub fn debug_fault_status() -> DebugFaultStatus {
+        // NOTE(unsafe) atomic read with no side effects
+        DebugFaultStatus::from_bits(unsafe { (*Self::PTR).dfsr.read() })
+    }
+
+    /// Clears the flags of the Debug Fault Status Register that are set in `status`
+    ///
+    /// The flags are write-one-to-clear, so passing the value returned by
+    /// [`SCB::debug_fault_status`] clears exactly the events that were observed.
+    #[inline]
+    pub fn clear_debug_fault_status(status: DebugFaultStatus) {
+        // NOTE(unsafe) atomic write-one-to-clear; other flags are unaffected
+        unsafe { (*Self::PTR).dfsr.write(status.bits()) }
+    }
+}
+
 /// System handlers, exceptions with configurable priority
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
 #[repr(u8)]
@@ -1110,6 +1201,30 @@ impl SCB {
         }
     }
 
+    /// Returns the logical priority level of `system_handler`, on a device implementing `BITS`
+    /// priority bits
+    ///
+    /// See [`Priority`] for the mapping between logical levels and hardware priorities.
+    #[inline]
+    pub fn priority_level<const BITS: u8>(system_handler: SystemHandler) -> Priority<BITS> {
+        Priority::from_hw(Self::get_priority(system_handler))
+    }
+
+    /// Sets the logical priority level of `system_handler` to `prio`
+    ///
+    /// # Unsafety
+    ///
+    /// Changing priority levels can break priority-based critical sections (see
+    /// [`register::basepri`](crate::register::basepri)) and compromise memory safety.
+    #[inline]
+    pub unsafe fn set_priority_level<const BITS: u8>(
+        &mut self,
+        system_handler: SystemHandler,
+        prio: Priority<BITS>,
+    ) {
+        unsafe { self.set_priority(system_handler, prio.to_hw()) }
+    }
+
     /// Return the bit position of the exception enable bit in the SHCSR register
     #[inline]
     #[cfg(not(any(armv6m, armv8m_base)))]
@@ -54,6 +54,79 @@ fn dwt() {
     assert_eq!(address(&dwt.lsr), 0xE000_1FB4);
 }
 
+#[test]
+#[cfg(not(feature = "cm7"))]
+fn etm() {
+    let etm = unsafe { &*crate::peripheral::ETM::PTR };
+
+    assert_eq!(address(&etm.cr), 0xE004_1000);
+    assert_eq!(address(&etm.ccr), 0xE004_1004);
+    assert_eq!(address(&etm.trigger), 0xE004_1008);
+    assert_eq!(address(&etm.sr), 0xE004_1010);
+    assert_eq!(address(&etm.scr), 0xE004_1014);
+    assert_eq!(address(&etm.teevr), 0xE004_1020);
+    assert_eq!(address(&etm.tecr1), 0xE004_1024);
+    assert_eq!(address(&etm.fflr), 0xE004_102C);
+    assert_eq!(address(&etm.cntrldvr1), 0xE004_1140);
+    assert_eq!(address(&etm.syncfr), 0xE004_11E0);
+    assert_eq!(address(&etm.idr), 0xE004_11E4);
+    assert_eq!(address(&etm.ccer), 0xE004_11E8);
+    assert_eq!(address(&etm.tesseicr), 0xE004_11F0);
+    assert_eq!(address(&etm.tsevr), 0xE004_11F8);
+    assert_eq!(address(&etm.traceidr), 0xE004_1200);
+    assert_eq!(address(&etm.idr2), 0xE004_1208);
+    assert_eq!(address(&etm.pdsr), 0xE004_1314);
+    assert_eq!(address(&etm.itctrl), 0xE004_1F00);
+    assert_eq!(address(&etm.claimset), 0xE004_1FA0);
+    assert_eq!(address(&etm.claimclr), 0xE004_1FA4);
+    assert_eq!(address(&etm.lar), 0xE004_1FB0);
+    assert_eq!(address(&etm.lsr), 0xE004_1FB4);
+    assert_eq!(address(&etm.authstatus), 0xE004_1FB8);
+    assert_eq!(address(&etm.devtype), 0xE004_1FCC);
+    assert_eq!(address(&etm.pidr), 0xE004_1FD0);
+    assert_eq!(address(&etm.cidr), 0xE004_1FF0);
+}
+
+#[test]
+#[cfg(feature = "cm7")]
+fn etm() {
+    let etm = unsafe { &*crate::peripheral::ETM::PTR };
+
+    assert_eq!(address(&etm.prgctlr), 0xE004_1004);
+    assert_eq!(address(&etm.statr), 0xE004_100C);
+    assert_eq!(address(&etm.configr), 0xE004_1010);
+    assert_eq!(address(&etm.auxctlr), 0xE004_1018);
+    assert_eq!(address(&etm.eventctl0r), 0xE004_1020);
+    assert_eq!(address(&etm.eventctl1r), 0xE004_1024);
+    assert_eq!(address(&etm.stallctlr), 0xE004_102C);
+    assert_eq!(address(&etm.tsctlr), 0xE004_1030);
+    assert_eq!(address(&etm.syncpr), 0xE004_1034);
+    assert_eq!(address(&etm.ccctlr), 0xE004_1038);
+    assert_eq!(address(&etm.bbctlr), 0xE004_103C);
+    assert_eq!(address(&etm.traceidr), 0xE004_1040);
+    assert_eq!(address(&etm.victlr), 0xE004_1080);
+    assert_eq!(address(&etm.viiectlr), 0xE004_1084);
+    assert_eq!(address(&etm.vissctlr), 0xE004_1088);
+    assert_eq!(address(&etm.vipcssctlr), 0xE004_108C);
+    assert_eq!(address(&etm.idr8), 0xE004_1180);
+    assert_eq!(address(&etm.idr), 0xE004_11E0);
+    assert_eq!(address(&etm.rsctlr), 0xE004_1200);
+    assert_eq!(address(&etm.oslar), 0xE004_1300);
+    assert_eq!(address(&etm.oslsr), 0xE004_1304);
+    assert_eq!(address(&etm.pdcr), 0xE004_1310);
+    assert_eq!(address(&etm.pdsr), 0xE004_1314);
+    assert_eq!(address(&etm.itctrl), 0xE004_1F00);
+    assert_eq!(address(&etm.claimset), 0xE004_1FA0);
+    assert_eq!(address(&etm.lar), 0xE004_1FB0);
+    assert_eq!(address(&etm.lsr), 0xE004_1FB4);
+    assert_eq!(address(&etm.authstatus), 0xE004_1FB8);
+    assert_eq!(address(&etm.devarch), 0xE004_1FBC);
+    assert_eq!(address(&etm.devid), 0xE004_1FC8);
+    assert_eq!(address(&etm.devtype), 0xE004_1FCC);
+    assert_eq!(address(&etm.pidr), 0xE004_1FD0);
+    assert_eq!(address(&etm.cidr), 0xE004_1FF0);
+}
+
 #[test]
 fn fpb() {
     let fpb = unsafe { &*crate::peripheral::FPB::PTR };
@@ -107,6 +180,26 @@ fn mpu() {
     assert_eq!(address(&mpu.rasr_a3), 0xE000EDB8);
 }
 
+#[test]
+fn mtb() {
+    let mtb = unsafe { crate::peripheral::MTB::new(0xF000_0000) };
+
+    assert_eq!(address(&mtb.position), 0xF000_0000);
+    assert_eq!(address(&mtb.master), 0xF000_0004);
+    assert_eq!(address(&mtb.flow), 0xF000_0008);
+    assert_eq!(address(&mtb.base), 0xF000_000C);
+    assert_eq!(address(&mtb.itctrl), 0xF000_0F00);
+    assert_eq!(address(&mtb.claimset), 0xF000_0FA0);
+    assert_eq!(address(&mtb.claimclr), 0xF000_0FA4);
+    assert_eq!(address(&mtb.lar), 0xF000_0FB0);
+    assert_eq!(address(&mtb.lsr), 0xF000_0FB4);
+    assert_eq!(address(&mtb.authstatus), 0xF000_0FB8);
+    assert_eq!(address(&mtb.devid), 0xF000_0FC8);
+    assert_eq!(address(&mtb.devtype), 0xF000_0FCC);
+    assert_eq!(address(&mtb.pidr), 0xF000_0FD0);
+    assert_eq!(address(&mtb.cidr), 0xF000_0FF0);
+}
+
 #[test]
 fn nvic() {
     let nvic = unsafe { &*crate::peripheral::NVIC::PTR };
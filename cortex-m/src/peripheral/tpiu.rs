@@ -4,6 +4,19 @@
 
 use volatile_register::{RO, RW, WO};
 
+use crate::peripheral::TPIU;
+
+const TPIU_SPPR_TXMODE_MASK: u32 = 0b11;
+const TPIU_FFCR_ENFCONT: u32 = 1 << 1;
+const TPIU_FFCR_TRIGIN: u32 = 1 << 8;
+const TPIU_TYPE_PTINVALID: u32 = 1 << 9;
+const TPIU_TYPE_MANCVALID: u32 = 1 << 10;
+const TPIU_TYPE_NRZVALID: u32 = 1 << 11;
+const TPIU_LAR_KEY: u32 = 0xC5AC_CE55;
+
+/// Relative deviation from the requested SWO baud rate tolerated by UART receivers, in percent
+const SWO_BAUD_RATE_TOLERANCE: u32 = 3;
+
 /// Register block
 #[repr(C)]
 pub struct RegisterBlock {
@@ -29,3 +42,194 @@ pub struct RegisterBlock {
     /// TPIU Type
     pub _type: RO<u32>,
 }
+
+/// Trace output protocol (SPPR TXMODE)
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum TraceProtocol {
+    /// Synchronous parallel trace port
+    Parallel = 0b00,
+    /// Serial wire output with Manchester encoding
+    SwoManchester = 0b01,
+    /// Serial wire output with NRZ encoding, compatible with a UART
+    SwoNrz = 0b10,
+}
+
+/// Possible errors of the TPIU configuration methods
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum TpiuError {
+    /// The trace protocol is not implemented
+    UnsupportedProtocol,
+    /// The parallel trace port width is not implemented
+    UnsupportedPortSize,
+    /// The SWO baud rate can not be derived from the trace clock within the tolerance of UART
+    /// receivers
+    BaudRateUnreachable,
+}
+
+impl TPIU {
+    /// Removes the software lock on the TPIU registers, if implemented
+    #[inline]
+    pub fn unlock(&mut self) {
+        unsafe { self.lar.write(TPIU_LAR_KEY) }
+    }
+
+    /// Returns `true` if the trace protocol is implemented (TYPE)
+    #[inline]
+    pub fn supports_protocol(protocol: TraceProtocol) -> bool {
+        // NOTE(unsafe) atomic read with no side effects
+        let ty = unsafe { (*Self::PTR)._type.read() };
+        match protocol {
+            TraceProtocol::Parallel => ty & TPIU_TYPE_PTINVALID == 0,
+            TraceProtocol::SwoManchester => ty & TPIU_TYPE_MANCVALID != 0,
+            TraceProtocol::SwoNrz => ty & TPIU_TYPE_NRZVALID != 0,
+        }
+    }
+
+    /// Returns the selected trace protocol, or `None` for a reserved value
+    #[inline]
+    pub fn protocol() -> Option<TraceProtocol> {
+        // NOTE(unsafe) atomic read with no side effects
+        match unsafe { (*Self::PTR).sppr.read() } & TPIU_SPPR_TXMODE_MASK {
+            0b00 => Some(TraceProtocol::Parallel),
+            0b01 => Some(TraceProtocol::SwoManchester),
+            0b10 => Some(TraceProtocol::SwoNrz),
+            _ => None,
+        }
+    }
+
+    /// Selects the trace protocol (SPPR)
+    #[inline]
+    pub fn set_protocol(&mut self, protocol: TraceProtocol) -> Result<(), TpiuError> {
+        if !Self::supports_protocol(protocol) {
+            return Err(TpiuError::UnsupportedProtocol);
+        }
+        unsafe { self.sppr.write(protocol as u32) }
+        Ok(())
+    }
+
+    /// Returns the supported parallel trace port widths (SSPSR)
+    ///
+    /// Bit `n - 1` is set if a width of `n` bits is supported.
+    #[inline]
+    pub fn supported_port_sizes() -> u32 {
+        // NOTE(unsafe) atomic read with no side effects
+        unsafe { (*Self::PTR).sspsr.read() }
+    }
+
+    /// Returns `true` if a parallel trace port of `width` bits is supported
+    #[inline]
+    pub fn supports_port_size(width: u8) -> bool {
+        (1..=32).contains(&width) && Self::supported_port_sizes() & (1 << (width - 1)) != 0
+    }
+
+    /// Selects the width of the parallel trace port in bits (CSPSR)
+    #[inline]
+    pub fn set_port_size(&mut self, width: u8) -> Result<(), TpiuError> {
+        if !Self::supports_port_size(width) {
+            return Err(TpiuError::UnsupportedPortSize);
+        }
+        unsafe { self.cspsr.write(1 << (width - 1)) }
+        Ok(())
+    }
+
+    /// Computes the ACPR prescaler that derives the SWO `baud_rate` from `trace_clk_hz`
+    ///
+    /// The SWO output runs at `trace_clk_hz / (prescaler + 1)`. An error is returned if the
+    /// resulting rate deviates by more than 3 % from `baud_rate`, which UART receivers do not
+    /// tolerate.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use cortex_m::peripheral::TPIU;
+    ///
+    /// assert_eq!(TPIU::swo_prescaler(72_000_000, 2_000_000), Ok(35));
+    /// assert!(TPIU::swo_prescaler(72_000_000, 100_000_000).is_err());
+    /// assert_eq!(TPIU::swo_prescaler(u32::MAX, u32::MAX), Ok(0));
+    /// ```
+    #[inline]
+    pub fn swo_prescaler(trace_clk_hz: u32, baud_rate: u32) -> Result<u16, TpiuError> {
+        if baud_rate == 0 || baud_rate > trace_clk_hz {
+            return Err(TpiuError::BaudRateUnreachable);
+        }
+        let (trace_clk_hz, baud_rate) = (u64::from(trace_clk_hz), u64::from(baud_rate));
+        // round to the nearest divisor; computed in 64 bits, as the sum can exceed `u32::MAX`
+        let divisor = (trace_clk_hz + baud_rate / 2) / baud_rate;
+        let prescaler = u16::try_from(divisor - 1).map_err(|_| TpiuError::BaudRateUnreachable)?;
+
+        let actual = trace_clk_hz / divisor;
+        if actual.abs_diff(baud_rate) * 100 > baud_rate * u64::from(SWO_BAUD_RATE_TOLERANCE) {
+            return Err(TpiuError::BaudRateUnreachable);
+        }
+        Ok(prescaler)
+    }
+
+    /// Sets the ACPR prescaler of the SWO output
+    #[inline]
+    pub fn set_prescaler(&mut self, prescaler: u16) {
+        unsafe { self.acpr.write(u32::from(prescaler)) }
+    }
+
+    /// Sets the SWO baud rate, given the frequency of the trace clock
+    ///
+    /// Returns `Err(TpiuError::BaudRateUnreachable)` if the rate can not be derived from the
+    /// trace clock, see `TPIU::swo_prescaler()`, or if the prescaler is wider than implemented.
+    #[inline]
+    pub fn set_swo_baud_rate(
+        &mut self,
+        trace_clk_hz: u32,
+        baud_rate: u32,
+    ) -> Result<(), TpiuError> {
+        let prescaler = Self::swo_prescaler(trace_clk_hz, baud_rate)?;
+        self.set_prescaler(prescaler);
+        if self.acpr.read() != u32::from(prescaler) {
+            return Err(TpiuError::BaudRateUnreachable);
+        }
+        Ok(())
+    }
+
+    /// Enables or disables the formatter (FFCR EnFCont)
+    ///
+    /// The formatter interleaves the packets of multiple trace sources, such as the ITM and the
+    /// ETM. With the formatter disabled, the SWO output carries the ITM and DWT packets
+    /// unchanged, as expected by most SWO viewers.
+    #[inline]
+    pub fn set_formatter(&mut self, enable: bool) {
+        self.set_ffcr_bit(TPIU_FFCR_ENFCONT, enable);
+    }
+
+    /// Enables or disables the indication of trigger events in the formatted stream (FFCR
+    /// TrigIn)
+    #[inline]
+    pub fn set_trigger_in(&mut self, enable: bool) {
+        self.set_ffcr_bit(TPIU_FFCR_TRIGIN, enable);
+    }
+
+    /// Configures the SWO output for the ITM and DWT packets
+    ///
+    /// This unlocks the TPIU, selects `protocol`, sets the baud rate and disables the formatter.
+    /// The `DCB::enable_trace()` method must have been called before.
+    #[inline]
+    pub fn configure_swo(
+        &mut self,
+        protocol: TraceProtocol,
+        trace_clk_hz: u32,
+        baud_rate: u32,
+    ) -> Result<(), TpiuError> {
+        if protocol == TraceProtocol::Parallel {
+            return Err(TpiuError::UnsupportedProtocol);
+        }
+        self.unlock();
+        self.set_protocol(protocol)?;
+        self.set_swo_baud_rate(trace_clk_hz, baud_rate)?;
+        self.set_formatter(false);
+        Ok(())
+    }
+
+    #[inline]
+    fn set_ffcr_bit(&mut self, bit: u32, value: bool) {
+        unsafe {
+            self.ffcr.modify(|r| if value { r | bit } else { r & !bit });
+        }
+    }
+}
@@ -0,0 +1,122 @@
+//! Logical priority levels
+//!
+//! The NVIC and the SCB encode priorities in the most significant bits of a byte, and a lower
+//! value means a higher priority (urgency). Only the `BITS` most significant bits are
+//! implemented, where `BITS` is device specific: 2 on ARMv6-M and ARMv8-M Baseline, and 3 to 8
+//! on ARMv7-M and ARMv8-M Mainline (often named `NVIC_PRIO_BITS` by device crates).
+//!
+//! [`Priority`] instead represents logical levels, from 0, the lowest priority, to
+//! `2^BITS - 1`, the highest, and orders them by urgency.
+//!
+//! # Examples
+//!
+//! ```
+//! use cortex_m::priority::Priority;
+//!
+//! type Prio = Priority<4>;
+//!
+//! // the level is checked at compile time
+//! let low = Prio::new::<1>();
+//! let high = Prio::new::<15>();
+//!
+//! assert!(high > low);
+//! assert_eq!(high.to_hw(), 0x00);
+//! assert_eq!(low.to_hw(), 0xe0);
+//! assert_eq!(Prio::from_hw(0xe0), low);
+//! ```
+//!
+//! A level outside of the range is rejected at compile time:
+//!
+//! ``` compile_fail
+//! use cortex_m::priority::Priority;
+//!
+//! let prio = Priority::<3>::new::<8>();
+//! ```
+
+use core::fmt;
+
+/// A logical priority level, on a device implementing `BITS` priority bits
+///
+/// Priorities compare by urgency: a greater `Priority` preempts a lower one.
+#[derive(Clone, Copy, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct Priority<const BITS: u8> {
+    level: u8,
+}
+
+impl<const BITS: u8> Priority<BITS> {
+    /// Highest logical level, `2^BITS - 1`
+    pub const MAX_LEVEL: u8 = {
+        assert!(
+            BITS >= 1 && BITS <= 8,
+            "devices implement 1 to 8 priority bits"
+        );
+        ((1u16 << BITS) - 1) as u8
+    };
+
+    /// Lowest priority, level 0
+    pub const LOWEST: Self = Priority { level: 0 };
+
+    /// Highest configurable priority, level `MAX_LEVEL`
+    pub const HIGHEST: Self = Priority {
+        level: Self::MAX_LEVEL,
+    };
+
+    /// Returns the priority of logical level `LEVEL`, which is checked at compile time
+    #[inline]
+    pub const fn new<const LEVEL: u8>() -> Self {
+        const {
+            assert!(
+                LEVEL <= Self::MAX_LEVEL,
+                "the priority level is out of range"
+            )
+        };
+        Priority { level: LEVEL }
+    }
+
+    /// Returns the priority of logical level `level`, or `None` if it is greater than
+    /// `MAX_LEVEL`
+    #[inline]
+    pub const fn try_new(level: u8) -> Option<Self> {
+        if level <= Self::MAX_LEVEL {
+            Some(Priority { level })
+        } else {
+            None
+        }
+    }
+
+    /// Returns the logical level
+    #[inline]
+    pub const fn level(self) -> u8 {
+        self.level
+    }
+
+    /// Returns the hardware encoding of the priority, as used by `NVIC::set_priority`,
+    /// `SCB::set_priority` and `basepri`
+    #[inline]
+    pub const fn to_hw(self) -> u8 {
+        (Self::MAX_LEVEL - self.level) << (8 - BITS)
+    }
+
+    /// Returns the logical level of the hardware priority `hw`
+    ///
+    /// The bits that are not implemented are ignored.
+    #[inline]
+    pub const fn from_hw(hw: u8) -> Self {
+        Priority {
+            level: Self::MAX_LEVEL - (hw >> (8 - BITS)),
+        }
+    }
+
+    /// Returns `true` if the priority preempts code running at priority `other`
+    #[inline]
+    pub const fn preempts(self, other: Self) -> bool {
+        self.level > other.level
+    }
+}
+
+impl<const BITS: u8> fmt::Debug for Priority<BITS> {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Priority({}/{})", self.level, Self::MAX_LEVEL)
+    }
+}
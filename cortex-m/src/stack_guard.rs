@@ -0,0 +1,177 @@
+//! Stack overflow detection using a DWT watchpoint
+//!
+//! A [`StackGuard`] arms a DWT comparator as a write watchpoint over the lowest bytes of a stack,
+//! and enables the `DebugMonitor` exception. Once the stack grows into the guarded region, the
+//! write raises `DebugMonitor`, whose handler can report the overflow together with the frame
+//! stacked at the time of the exception.
+//!
+//! This is useful on parts without an MPU, as ARMv7-M has no stack limit registers.
+//!
+//! **NOTE** This module is only available on ARMv7-M. ARMv6-M and ARMv8-M Baseline have no
+//! `DebugMonitor` exception, and ARMv8-M Mainline should use the MSPLIM and PSPLIM registers
+//! instead.
+//!
+//! # Limitations
+//!
+//! - Debug events halt the processor instead of raising `DebugMonitor` while a debugger has
+//!   enabled halting debug. Use [`StackGuard::is_effective`] to detect this situation.
+//! - Debug events are ignored while executing at a priority equal to or higher than the one of
+//!   `DebugMonitor`. Give `DebugMonitor` the highest priority of the application to also catch
+//!   overflows in interrupt handlers.
+//! - DWT watchpoints are imprecise: the exception is taken a few instructions after the faulting
+//!   write. The `DebugMonitor` handler runs on the overflowing main stack, so the guarded region
+//!   must be large enough to also hold the stack frame of that handler.
+//!
+//! # Examples
+//!
+//! ``` no_run
+//! use cortex_m::peripheral::Peripherals;
+//! use cortex_m::stack_guard::StackGuard;
+//!
+//! const GUARD_COMPARATOR: u8 = 0;
+//!
+//! let mut p = Peripherals::take().unwrap();
+//! let guard = StackGuard::main_stack(GUARD_COMPARATOR, 256).unwrap();
+//! guard.arm(&mut p.DCB, &mut p.DWT).unwrap();
+//! assert!(StackGuard::is_effective(), "a debugger will halt on overflow instead");
+//! ```
+//!
+//! With `cortex-m-rt`, the `DebugMonitor` handler can receive the stacked exception frame:
+//!
+//! ``` ignore
+//! #[exception]
+//! fn DebugMonitor(frame: &ExceptionFrame) {
+//!     if DWT::has_matched(GUARD_COMPARATOR) {
+//!         panic!("stack overflow: {:?}", frame);
+//!     }
+//! }
+//! ```
+
+use crate::peripheral::dwt::{AccessType, DwtError, Watchpoint};
+use crate::peripheral::{DCB, DWT};
+use crate::psp::StackHandle;
+
+/// A DWT write watchpoint over the bottom of a stack
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct StackGuard {
+    comparator: u8,
+    address: u32,
+    size: u32,
+}
+
+impl StackGuard {
+    /// Creates a guard of `size` bytes at the bottom of the stack starting at `bottom`
+    ///
+    /// `size` must be a power of two. The guarded region starts at `bottom` rounded up to a
+    /// multiple of `size`, as required by the DWT address mask.
+    ///
+    /// `comparator` is the DWT comparator used by the guard, which must not be used for anything
+    /// else.
+    ///
+    /// Returns `Err(DwtError::UnsupportedSize)` if `size` is not a power of two, and
+    /// `Err(DwtError::UnalignedAddress)` if rounding `bottom` up goes past the end of the
+    /// address space.
+    #[inline]
+    pub fn new(comparator: u8, bottom: *const u32, size: u32) -> Result<Self, DwtError> {
+        if !size.is_power_of_two() {
+            return Err(DwtError::UnsupportedSize);
+        }
+        let address = (bottom as u32)
+            .checked_add(size - 1)
+            .ok_or(DwtError::UnalignedAddress)?
+            & !(size - 1);
+
+        Ok(StackGuard {
+            comparator,
+            address,
+            size,
+        })
+    }
+
+    /// Creates a guard of `size` bytes at the bottom of the main stack
+    ///
+    /// The bottom of the main stack is taken from the `_stack_end` symbol, as defined by the
+    /// `cortex-m-rt` linker script. See [`StackGuard::new`] for the possible errors.
+    #[inline]
+    pub fn main_stack(comparator: u8, size: u32) -> Result<Self, DwtError> {
+        unsafe extern "C" {
+            static _stack_end: u32;
+        }
+
+        Self::new(comparator, &raw const _stack_end, size)
+    }
+
+    /// Creates a guard of `size` bytes at the bottom of a [`Stack`](crate::psp::Stack)
+    ///
+    /// See [`StackGuard::new`] for the possible errors.
+    #[inline]
+    pub fn process_stack(
+        comparator: u8,
+        stack: &mut StackHandle,
+        size: u32,
+    ) -> Result<Self, DwtError> {
+        Self::new(comparator, stack.bottom(), size)
+    }
+
+    /// Returns the DWT comparator used by the guard
+    #[inline]
+    pub const fn comparator(&self) -> u8 {
+        self.comparator
+    }
+
+    /// Returns the first address of the guarded region
+    #[inline]
+    pub const fn address(&self) -> u32 {
+        self.address
+    }
+
+    /// Returns the size of the guarded region in bytes
+    #[inline]
+    pub const fn size(&self) -> u32 {
+        self.size
+    }
+
+    /// Arms the guard
+    ///
+    /// This enables tracing, monitor mode debugging and the DWT comparator of the guard.
+    #[inline]
+    pub fn arm(&self, dcb: &mut DCB, dwt: &mut DWT) -> Result<(), DwtError> {
+        dcb.enable_trace();
+        dwt.set_watchpoint(
+            self.comparator,
+            Watchpoint {
+                address: self.address,
+                size: self.size,
+                access: AccessType::WriteOnly,
+            },
+        )?;
+        dcb.enable_debug_monitor();
+        Ok(())
+    }
+
+    /// Disarms the guard
+    ///
+    /// Monitor mode debugging is left enabled, as other debug events may rely on it.
+    #[inline]
+    pub fn disarm(&self, dwt: &mut DWT) -> Result<(), DwtError> {
+        dwt.clear_watchpoint(self.comparator)
+    }
+
+    /// Returns `true` if the guarded region was written to since the last call
+    ///
+    /// Call this from the `DebugMonitor` handler to tell a stack overflow apart from other debug
+    /// events. See [`DWT::has_matched`] for details.
+    #[inline]
+    pub fn is_triggered(&self) -> bool {
+        DWT::has_matched(self.comparator)
+    }
+
+    /// Returns `true` if an overflow would raise the `DebugMonitor` exception
+    ///
+    /// This is `false` while a debugger has enabled halting debug, in which case the debugger
+    /// halts the processor on overflow instead.
+    #[inline]
+    pub fn is_effective() -> bool {
+        !DCB::is_debugger_attached()
+    }
+}
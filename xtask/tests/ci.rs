@@ -28,6 +28,8 @@ fn build(package: &str, target: &str, features: &[&str]) {
     println!("building {} for {} {:?}", package, target, features);
     let mut cargo = Command::new("cargo");
     cargo.args(&["build", "-p", package, "--target", target]);
+    // Required by the `critical-section-basepri` feature of `cortex-m`.
+    cargo.env("CORTEX_M_CS_BASEPRI_THRESHOLD", "0x80");
     for feat in features {
         cargo.args(&["--features", *feat]);
     }
@@ -44,10 +46,13 @@ fn build(package: &str, target: &str, features: &[&str]) {
 
 #[rustfmt::skip]
 static PACKAGE_FEATURES: &[(&str, &[&str], &[&str])] = &[
-    ("cortex-m", ALL_TARGETS, &["cm7-r0p1", "critical-section-single-core"]), // no `linker-plugin-lto` since it's experimental
+    ("cortex-m", ALL_TARGETS, &["cm7-r0p1", "critical-section-single-core", "critical-section-basepri", "critical-section-timing", "log"]), // no `linker-plugin-lto` since it's experimental
     ("cortex-m-semihosting", ALL_TARGETS, &["no-semihosting", "jlink-quirks"]),
     ("panic-semihosting", ALL_TARGETS, &["exit", "jlink-quirks"]),
     ("panic-itm", NON_BASE_TARGETS, &[]),
+    ("cortex-m-gdbstub", NON_BASE_TARGETS, &[]),
+    ("cortex-m-trace-decoder", ALL_TARGETS, &["alloc"]),
+    ("cortex-m-dispatcher", ALL_TARGETS, &[]),
 ];
 
 fn check_crates_build(is_nightly: bool, is_msrv: bool) {
@@ -62,6 +67,12 @@ fn check_crates_build(is_nightly: bool, is_msrv: bool) {
                     "inline-asm" => is_nightly,
                     // This only affects thumbv7em targets.
                     "cm7-r0p1" => target.starts_with("thumbv7em"),
+                    // This requires BASEPRI.
+                    "critical-section-basepri" => {
+                        !target.starts_with("thumbv6m") && !target.starts_with("thumbv8m.base")
+                    }
+                    // This requires the DWT cycle counter.
+                    "critical-section-timing" => !target.starts_with("thumbv6m"),
 
                     _ => true,
                 }
@@ -83,8 +94,14 @@ fn check_crates_build(is_nightly: bool, is_msrv: bool) {
                 build(package, target, &[*feat]);
             }
 
-            // Every crate must build with *all* features enabled.
-            build(package, target, used_features);
+            // Every crate must build with *all* features enabled, except for the mutually
+            // exclusive `critical-section` implementations.
+            let all_features = used_features
+                .iter()
+                .copied()
+                .filter(|feat| *feat != "critical-section-basepri")
+                .collect::<Vec<_>>();
+            build(package, target, &all_features);
 
             // (technically we should be checking the powerset of all features if we wanted to be
             // *really* sure, but that takes too much time and isn't very easy to implement)